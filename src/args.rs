@@ -2,16 +2,133 @@
 
 use super::commands::Command;
 use super::error::Error;
+use clap::{CommandFactory, Parser};
+use std::ffi::OsString;
+use std::io;
+use tracing_subscriber::EnvFilter;
 
 #[derive(Clone, Debug, clap::Parser)]
 #[command(version, disable_help_subcommand = true)]
 pub struct Args {
+    /// Print diagnostic messages. Repeat for more detail.
+    ///
+    /// Without this option, the RUST_LOG environment variable is used.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
     #[command(subcommand)]
     command: Command,
 }
 
 impl Args {
+    /// Parses the arguments the process was started with.
+    ///
+    /// Clap can’t tell a dig-style `@server` from the query name, so these
+    /// arguments of the query command are turned into the hidden
+    /// `--at-server` option first.
+    pub fn from_env() -> Self {
+        Self::parse_from(Self::rewrite_at_server(std::env::args_os()))
+    }
+
     pub fn execute(self) -> Result<(), Error> {
+        self.init_tracing();
         self.command.execute()
     }
+
+    /// Turns `@server` arguments of the query command into options.
+    ///
+    /// The arguments may appear anywhere among the query name and types.
+    /// Values of options and everything after `--` are left alone.
+    fn rewrite_at_server(
+        args: impl IntoIterator<Item = OsString>,
+    ) -> Vec<OsString> {
+        let mut command = Self::command();
+        command.build();
+        let mut args = args.into_iter();
+        let mut res = args.next().into_iter().collect::<Vec<_>>();
+
+        // Everything up to and including the subcommand stays as is.
+        let mut is_query = false;
+        for arg in args.by_ref() {
+            let is_command = !arg.to_string_lossy().starts_with('-');
+            is_query = arg == "query";
+            res.push(arg);
+            if is_command {
+                break;
+            }
+        }
+        let query = match command.find_subcommand("query") {
+            Some(query) if is_query => query,
+            _ => {
+                res.extend(args);
+                return res;
+            }
+        };
+
+        let mut is_value = false;
+        for arg in args.by_ref() {
+            let text = arg.to_string_lossy().into_owned();
+            if is_value {
+                is_value = false;
+            } else if text == "--" {
+                res.push(arg);
+                break;
+            } else if let Some(server) =
+                text.strip_prefix('@').filter(|server| !server.is_empty())
+            {
+                res.push(format!("--at-server={}", server).into());
+                continue;
+            } else {
+                is_value = Self::needs_value(query, &text);
+            }
+            res.push(arg);
+        }
+        res.extend(args);
+        res
+    }
+
+    /// Returns whether an argument is an option followed by its value.
+    fn needs_value(command: &clap::Command, arg: &str) -> bool {
+        let takes_values = |arg: &clap::Arg| arg.get_action().takes_values();
+        if let Some(long) = arg.strip_prefix("--") {
+            !long.contains('=')
+                && command.get_arguments().any(|arg| {
+                    (arg.get_long() == Some(long)
+                        || arg
+                            .get_all_aliases()
+                            .is_some_and(|aliases| aliases.contains(&long)))
+                        && takes_values(arg)
+                })
+        } else if let Some(shorts) = arg.strip_prefix('-') {
+            // The first option of a cluster that takes a value consumes
+            // the rest of the cluster or, if there is none, the next
+            // argument.
+            for (i, ch) in shorts.char_indices() {
+                let arg = command
+                    .get_arguments()
+                    .find(|arg| arg.get_short() == Some(ch));
+                if arg.is_some_and(takes_values) {
+                    return i + ch.len_utf8() == shorts.len();
+                }
+            }
+            false
+        } else {
+            false
+        }
+    }
+
+    /// Sets up diagnostic output according to the verbosity.
+    fn init_tracing(&self) {
+        let filter = match self.verbose {
+            0 => EnvFilter::from_default_env(),
+            1 => EnvFilter::new("warn"),
+            2 => EnvFilter::new("info"),
+            3 => EnvFilter::new("debug"),
+            _ => EnvFilter::new("trace"),
+        };
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(io::stderr)
+            .init();
+    }
 }
@@ -7,16 +7,42 @@ use domain::base::message::Message;
 use domain::base::message_builder::MessageBuilder;
 use domain::base::name::ToName;
 use domain::base::question::Question;
-use domain::net::client::protocol::UdpConnect;
-use domain::net::client::request::{RequestMessage, SendRequest};
+use domain::net::client::protocol::AsyncConnect;
+use domain::net::client::request::{
+    ComposeRequest, RequestMessage, SendRequest,
+};
 use domain::net::client::{dgram, stream};
+use domain::rdata::tsig::Time48;
 use domain::resolv::stub::conf;
-use std::fmt;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use domain::tsig;
+use quinn::crypto::rustls::{HandshakeData, QuicClientConfig};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
-use tokio::net::TcpStream;
-use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use std::{fmt, fs};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+use tokio::task::JoinSet;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{
+    verify_tls12_signature, verify_tls13_signature, CryptoProvider,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{
+    ClientConfig, DigitallySignedStruct, ProtocolVersion, RootCertStore,
+    SignatureScheme,
+};
+use tracing::{debug, warn};
 
 //------------ Client --------------------------------------------------------
 
@@ -24,6 +50,12 @@ use tokio_rustls::rustls::{ClientConfig, RootCertStore};
 #[derive(Clone, Debug)]
 pub struct Client {
     servers: Vec<Server>,
+    recording: Option<Recording>,
+    insecure_tls: bool,
+    http_path: String,
+    connections: Option<Arc<ConnectionCache>>,
+    tsig_key: Option<Arc<tsig::Key>>,
+    race: bool,
 }
 
 impl Client {
@@ -41,13 +73,74 @@ impl Client {
                     retries: u8::try_from(conf.options.attempts).unwrap_or(2),
                     udp_payload_size: server.udp_payload_size,
                     tls_hostname: None,
+                    source: None,
+                    proxy: None,
+                    backoff: None,
                 })
                 .collect(),
+            recording: None,
+            insecure_tls: false,
+            http_path: DEFAULT_HTTP_PATH.into(),
+            connections: None,
+            tsig_key: None,
+            race: false,
         }
     }
 
     pub fn with_servers(servers: Vec<Server>) -> Self {
-        Self { servers }
+        Self {
+            servers,
+            recording: None,
+            insecure_tls: false,
+            http_path: DEFAULT_HTTP_PATH.into(),
+            connections: None,
+            tsig_key: None,
+            race: false,
+        }
+    }
+
+    /// Returns the servers the client sends requests to in order.
+    pub fn servers(&self) -> &[Server] {
+        &self.servers
+    }
+
+    /// Sets whether responses are recorded to or replayed from disk.
+    pub fn set_recording(&mut self, recording: Option<Recording>) {
+        self.recording = recording;
+    }
+
+    /// Sets whether TLS server certificates are accepted without checks.
+    pub fn set_insecure_tls(&mut self, insecure: bool) {
+        self.insecure_tls = insecure;
+    }
+
+    /// Sets the path of the URL used for DNS over HTTPS.
+    pub fn set_http_path(&mut self, path: String) {
+        self.http_path = path;
+    }
+
+    /// Sets whether TCP and TLS connections are kept open for reuse.
+    ///
+    /// If enabled, requests to the same server over the same transport
+    /// are pipelined over a single connection. The connections are closed
+    /// when the last clone of the client is dropped.
+    pub fn set_keep_connections(&mut self, keep: bool) {
+        self.connections = keep.then(Default::default);
+    }
+
+    /// Sets the key to sign requests with using TSIG.
+    ///
+    /// Signed requests can only be sent over UDP and TCP.
+    pub fn set_tsig_key(&mut self, key: Option<tsig::Key>) {
+        self.tsig_key = key.map(Arc::new);
+    }
+
+    /// Sets whether the servers are raced against each other.
+    ///
+    /// If enabled, requests are sent to the servers in the style of
+    /// RFC 8305’s happy eyeballs rather than trying one after the other.
+    pub fn set_race(&mut self, race: bool) {
+        self.race = race;
     }
 
     pub async fn query<N: ToName, Q: Into<Question<N>>>(
@@ -69,19 +162,137 @@ impl Client {
         &self,
         request: RequestMessage<Vec<u8>>,
     ) -> Result<Answer, Error> {
-        let mut servers = self.servers.as_slice();
-        while let Some((server, tail)) = servers.split_first() {
+        if let Some(Recording::Replay(dir)) = &self.recording {
+            return Recording::replay(dir, &request);
+        }
+        if self.servers.is_empty() {
+            return Err(Error::config("no servers to send the request to"));
+        }
+        if self.race && self.servers.len() > 1 {
+            return self.request_race(request).await;
+        }
+        let mut errors = Vec::new();
+        for server in &self.servers {
             match self.request_server(request.clone(), server).await {
-                Ok(answer) => return Ok(answer),
+                Ok(answer) => {
+                    if let Some(Recording::Record(dir)) = &self.recording {
+                        Recording::record(dir, &request, &answer)?;
+                    }
+                    return Ok(answer);
+                }
                 Err(err) => {
-                    if tail.is_empty() {
-                        return Err(err);
+                    debug!("request to {} failed: {}", server.addr, err);
+                    errors.push((server, err));
+                }
+            }
+        }
+        Err(Self::combine_errors(errors))
+    }
+
+    /// Races the servers against each other.
+    ///
+    /// The servers are ordered alternating between IPv6 and IPv4,
+    /// starting with IPv6. Each server gets a head start of
+    /// [`RACE_DELAY`] before the request is sent to the next one, unless
+    /// the request to it fails earlier. The first answer wins and all
+    /// other requests are cancelled.
+    async fn request_race(
+        &self,
+        request: RequestMessage<Vec<u8>>,
+    ) -> Result<Answer, Error> {
+        let servers = Self::interleave_families(&self.servers);
+        let mut pending = servers.iter().enumerate().peekable();
+        let mut tasks = JoinSet::new();
+        let mut errors = Vec::new();
+        let mut start_next = true;
+        loop {
+            if start_next {
+                if let Some((i, server)) = pending.next() {
+                    debug!("racing request to {}", server.addr);
+                    let client = self.clone();
+                    let server = (*server).clone();
+                    let request = request.clone();
+                    tasks.spawn(async move {
+                        (i, client.request_server(request, &server).await)
+                    });
+                }
+            }
+            let res = if pending.peek().is_some() {
+                match tokio::time::timeout(RACE_DELAY, tasks.join_next())
+                    .await
+                {
+                    Ok(res) => res,
+                    Err(_) => {
+                        start_next = true;
+                        continue;
+                    }
+                }
+            } else {
+                tasks.join_next().await
+            };
+            match res {
+                Some(Ok((_, Ok(answer)))) => {
+                    tasks.abort_all();
+                    if let Some(Recording::Record(dir)) = &self.recording {
+                        Recording::record(dir, &request, &answer)?;
                     }
+                    return Ok(answer);
                 }
+                Some(Ok((i, Err(err)))) => {
+                    debug!("request to {} failed: {}", servers[i].addr, err);
+                    errors.push((servers[i], err));
+                }
+                Some(Err(err)) => return Err(err.to_string().into()),
+                None if pending.peek().is_none() => break,
+                None => {}
+            }
+            start_next = true;
+        }
+        Err(Self::combine_errors(errors))
+    }
+
+    /// Orders the servers alternating between IPv6 and IPv4.
+    ///
+    /// The order within each address family is kept.
+    fn interleave_families(servers: &[Server]) -> Vec<&Server> {
+        let (v6, v4): (Vec<_>, Vec<_>) =
+            servers.iter().partition(|server| server.addr.is_ipv6());
+        let mut v6 = v6.into_iter();
+        let mut v4 = v4.into_iter();
+        let mut res = Vec::with_capacity(servers.len());
+        loop {
+            match (v6.next(), v4.next()) {
+                (None, None) => break,
+                (a, b) => res.extend(a.into_iter().chain(b)),
+            }
+        }
+        res
+    }
+
+    /// Combines the errors of all servers into one.
+    ///
+    /// A single error is returned as is. Otherwise, the message lists the
+    /// error for each server and the error is a transport error only if
+    /// all of them are.
+    fn combine_errors(mut errors: Vec<(&Server, Error)>) -> Error {
+        if errors.len() == 1 {
+            if let Some((_, err)) = errors.pop() {
+                return err;
             }
-            servers = tail;
         }
-        unreachable!()
+        let transport = errors
+            .iter()
+            .all(|(_, err)| err.exit_code() == Error::EXIT_TRANSPORT);
+        let message = errors
+            .iter()
+            .map(|(server, err)| format!("{}: {}", server.addr.ip(), err))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if transport {
+            Error::Transport(message.into())
+        } else {
+            Error::from(message)
+        }
     }
 
     pub async fn request_server(
@@ -89,12 +300,47 @@ impl Client {
         request: RequestMessage<Vec<u8>>,
         server: &Server,
     ) -> Result<Answer, Error> {
+        if let Some(key) = self.tsig_key.as_ref() {
+            return self.request_tsig(request, server, key.clone()).await;
+        }
         match server.transport {
             Transport::Udp => self.request_udp(request, server).await,
             Transport::UdpTcp => self.request_udptcp(request, server).await,
             Transport::Tcp => self.request_tcp(request, server).await,
             Transport::Tls => self.request_tls(request, server).await,
+            Transport::Https => self.request_https(request, server).await,
+            Transport::Quic => self.request_quic(request, server).await,
+            Transport::OpportunisticTls => {
+                self.request_opportunistic_tls(request, server).await
+            }
+        }
+    }
+
+    /// Tries TLS on port 853 first and falls back to UDP and TCP.
+    ///
+    /// The plain transports use the server’s address as is. TLS is only
+    /// tried if a host name to authenticate the server with is known.
+    pub async fn request_opportunistic_tls(
+        &self,
+        request: RequestMessage<Vec<u8>>,
+        server: &Server,
+    ) -> Result<Answer, Error> {
+        if server.tls_hostname.is_some() {
+            let mut tls_server = server.clone();
+            tls_server.addr.set_port(853);
+            match self.request_tls(request.clone(), &tls_server).await {
+                Ok(answer) => return Ok(answer),
+                Err(err) => {
+                    warn!(
+                        "TLS to {} failed, falling back: {}",
+                        tls_server.addr, err
+                    );
+                }
+            }
+        } else {
+            debug!("no TLS host name for {}, skipping TLS", server.addr);
         }
+        self.request_udptcp(request, server).await
     }
 
     pub async fn request_udptcp(
@@ -102,6 +348,10 @@ impl Client {
         request: RequestMessage<Vec<u8>>,
         server: &Server,
     ) -> Result<Answer, Error> {
+        if server.proxy.is_some() {
+            debug!("using TCP only for {} because of the proxy", server.addr);
+            return self.request_tcp(request, server).await;
+        }
         let answer = self.request_udp(request.clone(), server).await?;
         if answer.message.header().tc() {
             self.request_tcp(request, server).await
@@ -115,14 +365,51 @@ impl Client {
         request: RequestMessage<Vec<u8>>,
         server: &Server,
     ) -> Result<Answer, Error> {
+        Self::check_no_proxy(server)?;
+        if server.backoff.is_some() {
+            return self.request_udp_backoff(request, server).await;
+        }
+        debug!("sending request to {} via UDP", server.addr);
         let mut stats = Stats::new(server.addr, Protocol::Udp);
         let conn = dgram::Connection::with_config(
-            UdpConnect::new(server.addr),
+            BoundUdpConnect::new(server.bind_addr()?, server.addr),
             Self::dgram_config(server),
         );
         let message = conn.send_request(request).get_response().await?;
         stats.finalize();
-        Ok(Answer { message, stats })
+        Ok(Answer {
+            message,
+            stats,
+            tls: None,
+            server_name: None,
+        })
+    }
+
+    /// Sends a request via UDP with a backoff between retries.
+    ///
+    /// The datagram connection of the domain crate resends a request right
+    /// away, so this manages the retries itself.
+    async fn request_udp_backoff(
+        &self,
+        mut request: RequestMessage<Vec<u8>>,
+        server: &Server,
+    ) -> Result<Answer, Error> {
+        let mut stats = Stats::new(server.addr, Protocol::Udp);
+
+        // The datagram connection would set this for us.
+        request.set_udp_payload_size(server.udp_payload_size);
+        let data = request.to_vec()?;
+        let (response, attempts) = Self::send_udp(&data, server).await?;
+        stats.attempts = Some(attempts);
+        let message = Message::from_octets(Bytes::from(response))
+            .map_err(|_| "invalid response message")?;
+        stats.finalize();
+        Ok(Answer {
+            message,
+            stats,
+            tls: None,
+            server_name: None,
+        })
     }
 
     pub async fn request_tcp(
@@ -130,16 +417,66 @@ impl Client {
         request: RequestMessage<Vec<u8>>,
         server: &Server,
     ) -> Result<Answer, Error> {
+        if let Some(answer) =
+            self.request_kept(&request, server, Protocol::Tcp).await
+        {
+            return Ok(answer);
+        }
+        debug!("connecting to {} via TCP", server.addr);
         let mut stats = Stats::new(server.addr, Protocol::Tcp);
-        let socket = TcpStream::connect(server.addr).await?;
+        let socket = server.connect_tcp().await?;
+        stats.connected();
+        self.request_tcp_stream(request, socket, server, stats)
+            .await
+    }
+
+    /// Sends a request over an already connected TCP socket.
+    ///
+    /// This is intended for sockets handed to us from outside, so there is
+    /// no fallback to any other server or transport.
+    pub async fn request_stream(
+        &self,
+        request: RequestMessage<Vec<u8>>,
+        socket: std::net::TcpStream,
+        server: &Server,
+    ) -> Result<Answer, Error> {
+        socket.set_nonblocking(true)?;
+        let socket = TcpStream::from_std(socket)?;
+        match server.transport {
+            Transport::Tls => {
+                let stats = Stats::new(server.addr, Protocol::Tls);
+                self.request_tls_stream(request, socket, server, stats)
+                    .await
+            }
+            _ => {
+                let stats = Stats::new(server.addr, Protocol::Tcp);
+                self.request_tcp_stream(request, socket, server, stats)
+                    .await
+            }
+        }
+    }
+
+    async fn request_tcp_stream(
+        &self,
+        request: RequestMessage<Vec<u8>>,
+        socket: TcpStream,
+        server: &Server,
+        mut stats: Stats,
+    ) -> Result<Answer, Error> {
         let (conn, tran) = stream::Connection::with_config(
             socket,
             Self::stream_config(server),
         );
         tokio::spawn(tran.run());
+        self.keep_connection(server, Protocol::Tcp, &conn, None);
         let message = conn.send_request(request).get_response().await?;
         stats.finalize();
-        Ok(Answer { message, stats })
+        Ok(Answer {
+            message,
+            stats,
+            tls: None,
+            server_name: None,
+        })
     }
 
     pub async fn request_tls(
@@ -147,37 +484,452 @@ impl Client {
         request: RequestMessage<Vec<u8>>,
         server: &Server,
     ) -> Result<Answer, Error> {
-        let root_store = RootCertStore {
-            roots: webpki_roots::TLS_SERVER_ROOTS.into(),
-        };
-        let client_config = Arc::new(
-            ClientConfig::builder()
-                .with_root_certificates(root_store)
-                .with_no_client_auth(),
-        );
-
+        if let Some(answer) =
+            self.request_kept(&request, server, Protocol::Tls).await
+        {
+            return Ok(answer);
+        }
+        debug!("connecting to {} via TLS", server.addr);
         let mut stats = Stats::new(server.addr, Protocol::Tls);
-        let tcp_socket = TcpStream::connect(server.addr).await?;
-        let tls_connector = tokio_rustls::TlsConnector::from(client_config);
-        let server_name = server
-            .tls_hostname
-            .clone()
-            .expect("tls_hostname must be set for tls")
-            .try_into()
-            .map_err(|_| {
-                let s = "Invalid DNS name";
-                <&str as Into<Error>>::into(s)
-            })?;
-        let tls_socket =
-            tls_connector.connect(server_name, tcp_socket).await?;
+        let socket = server.connect_tcp().await?;
+        stats.connected();
+        self.request_tls_stream(request, socket, server, stats)
+            .await
+    }
+
+    async fn request_tls_stream(
+        &self,
+        request: RequestMessage<Vec<u8>>,
+        tcp_socket: TcpStream,
+        server: &Server,
+        mut stats: Stats,
+    ) -> Result<Answer, Error> {
+        let (tls_socket, tls) =
+            self.tls_connect(tcp_socket, server, Vec::new()).await?;
+        stats.tls_established();
         let (conn, tran) = stream::Connection::with_config(
             tls_socket,
             Self::stream_config(server),
         );
         tokio::spawn(tran.run());
+        self.keep_connection(server, Protocol::Tls, &conn, Some(&tls));
         let message = conn.send_request(request).get_response().await?;
         stats.finalize();
-        Ok(Answer { message, stats })
+        Ok(Answer {
+            message,
+            stats,
+            tls: Some(tls),
+            server_name: None,
+        })
+    }
+
+    /// Sends a TSIG-signed request over UDP or TCP.
+    ///
+    /// The connections of the domain crate may change the message ID after
+    /// we signed the request, so this sends the message itself. The
+    /// signature of the response is verified.
+    async fn request_tsig(
+        &self,
+        mut request: RequestMessage<Vec<u8>>,
+        server: &Server,
+        key: Arc<tsig::Key>,
+    ) -> Result<Answer, Error> {
+        // The datagram connection would set this for us.
+        if matches!(server.transport, Transport::Udp | Transport::UdpTcp) {
+            request.set_udp_payload_size(server.udp_payload_size);
+        }
+        let mut builder = request
+            .append_message(Vec::new())
+            .map_err(|_| "cannot create request")?;
+        let transaction = tsig::ClientTransaction::request(
+            key,
+            &mut builder,
+            Time48::now(),
+        )
+        .map_err(|err| format!("cannot sign request: {}", err))?;
+        let data = builder.finish();
+
+        let (response, mut stats) = match server.transport {
+            Transport::UdpTcp if server.proxy.is_some() => {
+                let stats = Stats::new(server.addr, Protocol::Tcp);
+                (Self::send_tcp(&data, server).await?, stats)
+            }
+            Transport::Udp | Transport::UdpTcp => {
+                Self::check_no_proxy(server)?;
+                let mut stats = Stats::new(server.addr, Protocol::Udp);
+                let (response, attempts) =
+                    Self::send_udp(&data, server).await?;
+                stats.attempts = Some(attempts);
+                if matches!(server.transport, Transport::UdpTcp)
+                    && Message::from_slice(&response)
+                        .is_ok_and(|msg| msg.header().tc())
+                {
+                    stats = Stats::new(server.addr, Protocol::Tcp);
+                    (Self::send_tcp(&data, server).await?, stats)
+                } else {
+                    (response, stats)
+                }
+            }
+            Transport::Tcp => {
+                let stats = Stats::new(server.addr, Protocol::Tcp);
+                (Self::send_tcp(&data, server).await?, stats)
+            }
+            _ => {
+                return Err(Error::config(
+                    "TSIG is only supported over UDP and TCP",
+                ))
+            }
+        };
+        stats.finalize();
+
+        let mut message = Message::from_octets(response.clone())
+            .map_err(|_| "invalid response message")?;
+        transaction
+            .answer(&mut message, Time48::now())
+            .map_err(|err| format!("TSIG verification failed: {}", err))?;
+        Ok(Answer {
+            message: Message::from_octets(Bytes::from(response))
+                .map_err(|_| "invalid response message")?,
+            stats,
+            tls: None,
+            server_name: None,
+        })
+    }
+
+    /// Sends a message via UDP and returns the response.
+    ///
+    /// Like with the datagram connection of the domain crate, responses
+    /// with a different message ID or question are ignored. Also returns
+    /// how often the message was sent.
+    ///
+    /// With a backoff, the wait for a response before each retry is
+    /// extended by the backoff delay, so a late response is still
+    /// accepted.
+    async fn send_udp(
+        data: &[u8],
+        server: &Server,
+    ) -> Result<(Vec<u8>, u8), Error> {
+        let socket = UdpSocket::bind(server.bind_addr()?).await?;
+        socket.connect(server.addr).await?;
+        let mut buf = vec![0; usize::from(u16::MAX)];
+        for attempt in 0..=server.retries {
+            let mut wait = server.timeout;
+            if let Some(backoff) = server.backoff {
+                if attempt < server.retries {
+                    wait += backoff.delay(attempt);
+                }
+            }
+            debug!(
+                "sending request to {} via UDP, attempt {}",
+                server.addr,
+                attempt + 1
+            );
+            socket.send(data).await?;
+            let recv = async {
+                loop {
+                    let len = socket.recv(&mut buf).await?;
+                    if Self::is_answer(data, &buf[..len]) {
+                        return Ok::<_, io::Error>(len);
+                    }
+                }
+            };
+            if let Ok(len) = tokio::time::timeout(wait, recv).await {
+                buf.truncate(len?);
+                return Ok((buf, attempt + 1));
+            }
+        }
+        Err(Error::timeout("timeout waiting for UDP response"))
+    }
+
+    /// Returns whether `response` answers `request`.
+    ///
+    /// This requires the message ID and the question to match.
+    fn is_answer(request: &[u8], response: &[u8]) -> bool {
+        match (
+            Message::from_octets(request),
+            Message::from_octets(response),
+        ) {
+            (Ok(request), Ok(response)) => response.is_answer(&request),
+            _ => false,
+        }
+    }
+
+    /// Sends a message via TCP and returns the response.
+    async fn send_tcp(
+        data: &[u8],
+        server: &Server,
+    ) -> Result<Vec<u8>, Error> {
+        debug!("sending signed request to {} via TCP", server.addr);
+        let len = u16::try_from(data.len())
+            .map_err(|_| "request too long")?
+            .to_be_bytes();
+        tokio::time::timeout(server.timeout, async {
+            let mut socket = server.connect_tcp().await?;
+            socket.write_all(&len).await?;
+            socket.write_all(data).await?;
+            let mut len = [0; 2];
+            socket.read_exact(&mut len).await?;
+            let mut res = vec![0; usize::from(u16::from_be_bytes(len))];
+            socket.read_exact(&mut res).await?;
+            Ok::<_, Error>(res)
+        })
+        .await
+        .map_err(|_| Error::timeout("timeout waiting for TCP response"))?
+    }
+
+    /// Sends a request over a kept connection if there is one.
+    ///
+    /// Returns `None` if connections aren’t kept, there is no connection
+    /// for the server yet, or the connection has failed. In the latter
+    /// case, the connection is dropped so the caller opens a new one.
+    async fn request_kept(
+        &self,
+        request: &RequestMessage<Vec<u8>>,
+        server: &Server,
+        protocol: Protocol,
+    ) -> Option<Answer> {
+        let cache = self.connections.as_ref()?;
+        let (conn, tls) = cache.get(server.addr, protocol)?;
+        debug!("reusing {} connection to {}", protocol, server.addr);
+        let mut stats = Stats::new(server.addr, protocol);
+        match conn.send_request(request.clone()).get_response().await {
+            Ok(message) => {
+                stats.finalize();
+                Some(Answer {
+                    message,
+                    stats,
+                    tls,
+                    server_name: None,
+                })
+            }
+            Err(err) => {
+                debug!(
+                    "kept connection to {} failed, reconnecting: {}",
+                    server.addr, err
+                );
+                cache.remove(server.addr, protocol);
+                None
+            }
+        }
+    }
+
+    /// Remembers a newly opened connection if connections are kept.
+    fn keep_connection(
+        &self,
+        server: &Server,
+        protocol: Protocol,
+        conn: &StreamConnection,
+        tls: Option<&TlsInfo>,
+    ) {
+        if let Some(cache) = self.connections.as_ref() {
+            cache.insert(server.addr, protocol, conn.clone(), tls.cloned());
+        }
+    }
+
+    /// Establishes a TLS session over a TCP socket.
+    ///
+    /// The protocols in `alpn` are offered via ALPN.
+    async fn tls_connect(
+        &self,
+        tcp_socket: TcpStream,
+        server: &Server,
+        alpn: Vec<Vec<u8>>,
+    ) -> Result<(TlsStream<TcpStream>, TlsInfo), Error> {
+        let client_config = Arc::new(self.tls_config(server, alpn));
+        let tls_connector = tokio_rustls::TlsConnector::from(client_config);
+        let tls_hostname = server
+            .tls_hostname
+            .clone()
+            .expect("tls_hostname must be set for tls");
+        let server_name = tls_hostname.clone().try_into().map_err(|_| {
+            Error::config(Self::invalid_tls_hostname(&tls_hostname))
+        })?;
+        debug!("starting TLS handshake with {}", tls_hostname);
+        let tls_socket = tls_connector
+            .connect(server_name, tcp_socket)
+            .await
+            .inspect_err(|err| warn!("TLS handshake failed: {}", err))?;
+        let tls = TlsInfo::new(tls_hostname, tls_socket.get_ref().1);
+        debug!(
+            "TLS session established: version {}",
+            tls.version.unwrap_or("unknown")
+        );
+        Ok((tls_socket, tls))
+    }
+
+    /// Returns the TLS configuration for connecting to a server.
+    ///
+    /// The protocols in `alpn` are offered via ALPN.
+    fn tls_config(
+        &self,
+        server: &Server,
+        alpn: Vec<Vec<u8>>,
+    ) -> ClientConfig {
+        let root_store = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.into(),
+        };
+        let mut client_config = ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        if self.insecure_tls {
+            warn!("not verifying the certificate of {}", server.addr);
+            let verifier = NoCertificateVerification(
+                client_config.crypto_provider().clone(),
+            );
+            client_config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(verifier));
+        }
+        client_config.alpn_protocols = alpn;
+        client_config
+    }
+
+    pub async fn request_quic(
+        &self,
+        request: RequestMessage<Vec<u8>>,
+        server: &Server,
+    ) -> Result<Answer, Error> {
+        let tls_hostname = server
+            .tls_hostname
+            .clone()
+            .expect("tls_hostname must be set for quic");
+        Self::check_no_proxy(server)?;
+        let tls_config = self.tls_config(server, vec![b"doq".to_vec()]);
+        let quic_config = QuicClientConfig::try_from(tls_config)
+            .map_err(|err| err.to_string())?;
+        let mut endpoint = quinn::Endpoint::client(server.bind_addr()?)?;
+        endpoint.set_default_client_config(quinn::ClientConfig::new(
+            Arc::new(quic_config),
+        ));
+
+        debug!("connecting to {} via QUIC", server.addr);
+        let mut stats = Stats::new(server.addr, Protocol::Quic);
+        let res = tokio::time::timeout(server.timeout, async {
+            // QUIC combines connecting and the TLS handshake. We consider
+            // the connection established once the server’s handshake data
+            // has arrived and the handshake complete when it is confirmed.
+            let mut connecting = endpoint
+                .connect(server.addr, &tls_hostname)
+                .map_err(|err| err.to_string())?;
+            let alpn = connecting
+                .handshake_data()
+                .await
+                .map_err(|err| err.to_string())?
+                .downcast::<HandshakeData>()
+                .ok()
+                .and_then(|data| data.protocol);
+            stats.connected();
+            let conn = connecting.await.map_err(|err| err.to_string())?;
+            stats.tls_established();
+
+            // RFC 9250 requires an ID of zero and a length prefix like TCP.
+            let mut data = request.to_vec()?;
+            data[..2].copy_from_slice(&[0, 0]);
+            let len = u16::try_from(data.len())
+                .map_err(|_| "request too long")?
+                .to_be_bytes();
+
+            // Every query gets a stream of its own.
+            let (mut send, mut recv) =
+                conn.open_bi().await.map_err(|err| err.to_string())?;
+            send.write_all(&len).await.map_err(|err| err.to_string())?;
+            send.write_all(&data).await.map_err(|err| err.to_string())?;
+            send.finish().map_err(|err| err.to_string())?;
+            let response = recv
+                .read_to_end(usize::from(u16::MAX) + 2)
+                .await
+                .map_err(|err| err.to_string())?;
+
+            // DOQ_NO_ERROR
+            conn.close(0u32.into(), b"");
+            Ok::<_, Error>((response, alpn))
+        })
+        .await
+        .map_err(|_| Error::timeout("timeout waiting for QUIC response"))?;
+        endpoint.wait_idle().await;
+        let (response, alpn) = res.map_err(Error::transport)?;
+
+        let message = quic_response_message(response)?;
+        stats.finalize();
+        Ok(Answer {
+            message,
+            stats,
+            tls: Some(TlsInfo {
+                server_name: tls_hostname,
+                version: Some("TLSv1.3"),
+                // Quinn doesn’t tell us which cipher suite was used.
+                cipher_suite: None,
+                alpn,
+            }),
+            server_name: None,
+        })
+    }
+
+    pub async fn request_https(
+        &self,
+        request: RequestMessage<Vec<u8>>,
+        server: &Server,
+    ) -> Result<Answer, Error> {
+        debug!("connecting to {} via HTTPS", server.addr);
+        let mut stats = Stats::new(server.addr, Protocol::Https);
+        let tcp_socket = server.connect_tcp().await?;
+        stats.connected();
+        let (mut tls_socket, tls) = self
+            .tls_connect(tcp_socket, server, vec![b"http/1.1".to_vec()])
+            .await?;
+        stats.tls_established();
+
+        // RFC 8484 asks for an ID of zero to make responses cacheable.
+        let mut body = request.to_vec()?;
+        body[..2].copy_from_slice(&[0, 0]);
+        let http_request =
+            http_request(&self.http_path, &tls.server_name, body.len());
+        let body = tokio::time::timeout(server.timeout, async {
+            tls_socket.write_all(http_request.as_bytes()).await?;
+            tls_socket.write_all(&body).await?;
+            tls_socket.flush().await?;
+            http_read_response(&mut tls_socket).await
+        })
+        .await
+        .map_err(|_| Error::timeout("timeout waiting for HTTP response"))??;
+
+        let message = Message::from_octets(Bytes::from(body))
+            .map_err(|_| "invalid DNS message in HTTP response")?;
+        stats.finalize();
+        Ok(Answer {
+            message,
+            stats,
+            tls: Some(tls),
+            server_name: None,
+        })
+    }
+
+    /// Fails if the server is to be reached through a proxy.
+    ///
+    /// This is for transports running over UDP which the proxy can’t
+    /// carry.
+    fn check_no_proxy(server: &Server) -> Result<(), Error> {
+        match server.proxy.as_ref() {
+            Some(proxy) => Err(Error::config(format!(
+                "proxy {} requires TCP, TLS, or HTTPS as the transport",
+                proxy
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns an error message for a TLS host name rustls didn’t accept.
+    fn invalid_tls_hostname(name: &str) -> String {
+        let hint = if name.is_empty() {
+            "the name must not be empty"
+        } else if name.ends_with('.') {
+            "try again without the trailing dot"
+        } else if name.contains(['[', ']', ':', '/']) {
+            "expected a DNS name or a bare IP address without port"
+        } else {
+            "expected a DNS name or an IP address"
+        };
+        format!("invalid TLS host name '{}': {}", name, hint)
     }
 
     fn dgram_config(server: &Server) -> dgram::Config {
@@ -195,6 +947,412 @@ impl Client {
     }
 }
 
+/// The path of the URL used for DNS over HTTPS unless configured.
+pub const DEFAULT_HTTP_PATH: &str = "/dns-query";
+
+/// How long a raced server gets before the next one is tried.
+///
+/// This is the connection attempt delay recommended by RFC 8305.
+const RACE_DELAY: Duration = Duration::from_millis(250);
+
+//------------ QUIC ----------------------------------------------------------
+
+/// Returns the message from the data received on a QUIC stream.
+///
+/// The message is preceded by its length as a 16 bit integer which has to
+/// match the length of the remaining data.
+fn quic_response_message(response: Vec<u8>) -> Result<Message<Bytes>, Error> {
+    let Some((len, data)) = response.split_first_chunk::<2>() else {
+        return Err("short QUIC response".into());
+    };
+    let len = usize::from(u16::from_be_bytes(*len));
+    if len != data.len() {
+        return Err(format!(
+            "QUIC response length mismatch: prefix says {} but got {} \
+             octets",
+            len,
+            data.len()
+        )
+        .into());
+    }
+    Message::from_octets(Bytes::from(response).slice(2..))
+        .map_err(|_| "invalid DNS message in QUIC response".into())
+}
+
+//------------ HTTP ----------------------------------------------------------
+
+/// Returns the header of an HTTP/1.1 request for DNS over HTTPS.
+///
+/// The request uses POST and asks the server to close the connection after
+/// the response.
+fn http_request(path: &str, host: &str, len: usize) -> String {
+    format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/dns-message\r\n\
+         Accept: application/dns-message\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n",
+        path, host, len
+    )
+}
+
+/// Reads an HTTP/1.1 response and returns its body.
+///
+/// Reading stops as soon as the body is complete rather than at the end
+/// of the connection, since many servers close TLS connections without
+/// sending a close_notify alert first.
+async fn http_read_response(
+    sock: &mut (impl AsyncRead + Unpin),
+) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let len = match sock.read(&mut buf).await {
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => 0,
+            Err(err) => return Err(err.into()),
+        };
+        data.extend_from_slice(&buf[..len]);
+        if let Some(body) = http_response_body(&data, len == 0)? {
+            return Ok(body);
+        }
+    }
+}
+
+/// Returns the body of an HTTP/1.1 response if it is complete.
+///
+/// Returns `Ok(None)` if more data is needed. If `eof` is true, no more
+/// data will arrive and an incomplete response is an error. Fails unless
+/// the status is 200 and the content type is that of a DNS message.
+/// Handles both bodies with a given length and chunked bodies.
+fn http_response_body(
+    data: &[u8],
+    eof: bool,
+) -> Result<Option<Vec<u8>>, Error> {
+    let Some(header_end) =
+        data.windows(4).position(|window| window == b"\r\n\r\n")
+    else {
+        return http_incomplete(eof);
+    };
+    let header = String::from_utf8_lossy(&data[..header_end]);
+    let body = &data[header_end + 4..];
+
+    let mut lines = header.split("\r\n");
+    let status = lines.next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("HTTP request failed: {}", status).into());
+    }
+
+    let mut chunked = false;
+    let mut len = None;
+    let mut content_type = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            len =
+                Some(usize::from_str(value).map_err(|_| {
+                    "invalid Content-Length in HTTP response"
+                })?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else if name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value);
+        }
+    }
+
+    // Parameters after a semicolon don’t change the media type.
+    let media_type = content_type
+        .and_then(|value| value.split(';').next())
+        .map(str::trim);
+    if !media_type.is_some_and(|media_type| {
+        media_type.eq_ignore_ascii_case("application/dns-message")
+    }) {
+        return Err(format!(
+            "unexpected content type in HTTP response: {}",
+            content_type.unwrap_or("none")
+        )
+        .into());
+    }
+
+    if chunked {
+        return match http_dechunk(body)? {
+            Some(body) => Ok(Some(body)),
+            None => http_incomplete(eof),
+        };
+    }
+    match len {
+        Some(len) => match body.get(..len) {
+            Some(body) => Ok(Some(body.into())),
+            None => http_incomplete(eof),
+        },
+        // Without a length, the body ends with the connection.
+        None if eof => Ok(Some(body.into())),
+        None => Ok(None),
+    }
+}
+
+/// Returns the result for an HTTP response that isn’t complete yet.
+fn http_incomplete(eof: bool) -> Result<Option<Vec<u8>>, Error> {
+    if eof {
+        Err("incomplete HTTP response".into())
+    } else {
+        Ok(None)
+    }
+}
+
+/// Decodes a body using the chunked transfer encoding.
+///
+/// Returns `Ok(None)` if the body isn’t complete yet.
+fn http_dechunk(mut data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    let mut res = Vec::new();
+    loop {
+        let Some(line_end) =
+            data.windows(2).position(|window| window == b"\r\n")
+        else {
+            return Ok(None);
+        };
+        let size = String::from_utf8_lossy(&data[..line_end]);
+        // Chunk extensions follow a semicolon and can be ignored.
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| "invalid chunk size in HTTP response")?;
+        if size == 0 {
+            return Ok(Some(res));
+        }
+        let Some(chunk) = data.get(line_end + 2..line_end + 2 + size) else {
+            return Ok(None);
+        };
+        res.extend_from_slice(chunk);
+        data = data.get(line_end + 4 + size..).unwrap_or_default();
+    }
+}
+
+//------------ NoCertificateVerification -------------------------------------
+
+/// A certificate verifier that accepts any certificate.
+///
+/// Signatures made during the handshake are still checked, so this only
+/// skips verifying that the certificate is valid for the server.
+#[derive(Debug)]
+struct NoCertificateVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+//------------ Recording -----------------------------------------------------
+
+/// Recording responses to or replaying them from a directory.
+///
+/// Each response is kept in a file of its own named after the question of
+/// the request.
+#[derive(Clone, Debug)]
+pub enum Recording {
+    /// Save every response received into the directory.
+    Record(PathBuf),
+
+    /// Answer requests from the responses saved in the directory.
+    Replay(PathBuf),
+}
+
+impl Recording {
+    fn record(
+        dir: &Path,
+        request: &RequestMessage<Vec<u8>>,
+        answer: &Answer,
+    ) -> Result<(), Error> {
+        let path = Self::path(dir, request)?;
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, answer.message.as_slice()))
+            .map_err(|err| {
+                format!("Failed to record to {}: {}", path.display(), err)
+                    .into()
+            })
+    }
+
+    fn replay(
+        dir: &Path,
+        request: &RequestMessage<Vec<u8>>,
+    ) -> Result<Answer, Error> {
+        let path = Self::path(dir, request)?;
+        let mut stats =
+            Stats::new(SocketAddr::from(([0, 0, 0, 0], 0)), Protocol::Replay);
+        let data = fs::read(&path).map_err(|err| {
+            format!("Failed to replay from {}: {}", path.display(), err)
+        })?;
+        let message =
+            Message::from_octets(Bytes::from(data)).map_err(|_| {
+                format!("Invalid recorded message in {}", path.display())
+            })?;
+        stats.finalize();
+        Ok(Answer {
+            message,
+            stats,
+            tls: None,
+            server_name: None,
+        })
+    }
+
+    /// Returns the path of the file for the given request.
+    fn path(
+        dir: &Path,
+        request: &RequestMessage<Vec<u8>>,
+    ) -> Result<PathBuf, Error> {
+        let request = Message::from_octets(request.to_vec()?)
+            .map_err(|_| "Cannot record an invalid request")?;
+        let question = request
+            .first_question()
+            .ok_or("Cannot record a request without a question")?;
+        let name = format!(
+            "{}-{}-{}.msg",
+            question.qname(),
+            question.qclass(),
+            question.qtype()
+        )
+        .to_lowercase()
+        .replace(['/', '\\'], "_");
+        Ok(dir.join(name))
+    }
+}
+
+//------------ ConnectionCache -----------------------------------------------
+
+/// A stream connection to a server.
+type StreamConnection = stream::Connection<RequestMessage<Vec<u8>>>;
+
+/// The kept connections by server address and whether they use TLS.
+type ConnectionMap =
+    HashMap<(SocketAddr, bool), (StreamConnection, Option<TlsInfo>)>;
+
+/// The stream connections kept open by a client.
+///
+/// Dropping a connection closes it once all outstanding requests are
+/// answered.
+#[derive(Default)]
+struct ConnectionCache {
+    conns: Mutex<ConnectionMap>,
+}
+
+impl ConnectionCache {
+    fn get(
+        &self,
+        addr: SocketAddr,
+        protocol: Protocol,
+    ) -> Option<(StreamConnection, Option<TlsInfo>)> {
+        self.lock().get(&Self::key(addr, protocol)).cloned()
+    }
+
+    fn insert(
+        &self,
+        addr: SocketAddr,
+        protocol: Protocol,
+        conn: StreamConnection,
+        tls: Option<TlsInfo>,
+    ) {
+        self.lock().insert(Self::key(addr, protocol), (conn, tls));
+    }
+
+    fn remove(&self, addr: SocketAddr, protocol: Protocol) {
+        self.lock().remove(&Self::key(addr, protocol));
+    }
+
+    fn key(addr: SocketAddr, protocol: Protocol) -> (SocketAddr, bool) {
+        (addr, matches!(protocol, Protocol::Tls))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ConnectionMap> {
+        self.conns.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl fmt::Debug for ConnectionCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionCache")
+            .field("len", &self.lock().len())
+            .finish()
+    }
+}
+
+//------------ BoundUdpConnect -----------------------------------------------
+
+/// Creates UDP sockets bound to a given local address.
+///
+/// This is like `UdpConnect` of the domain crate which always binds to the
+/// unspecified address.
+#[derive(Clone, Copy, Debug)]
+struct BoundUdpConnect {
+    local: SocketAddr,
+    remote: SocketAddr,
+}
+
+impl BoundUdpConnect {
+    fn new(local: SocketAddr, remote: SocketAddr) -> Self {
+        Self { local, remote }
+    }
+}
+
+impl AsyncConnect for BoundUdpConnect {
+    type Connection = UdpSocket;
+    type Fut = Pin<
+        Box<dyn Future<Output = Result<UdpSocket, io::Error>> + Send + Sync>,
+    >;
+
+    fn connect(&self) -> Self::Fut {
+        let BoundUdpConnect { local, remote } = *self;
+        Box::pin(async move {
+            let sock = UdpSocket::bind(local).await?;
+            sock.connect(remote).await?;
+            Ok(sock)
+        })
+    }
+}
+
 //------------ Server --------------------------------------------------------
 
 #[derive(Clone, Debug)]
@@ -205,6 +1363,267 @@ pub struct Server {
     pub retries: u8,
     pub udp_payload_size: u16,
     pub tls_hostname: Option<String>,
+
+    /// The local address to send requests from.
+    ///
+    /// If this is `None`, the system picks the address.
+    pub source: Option<SocketAddr>,
+
+    /// The SOCKS5 proxy to connect to the server through.
+    ///
+    /// If this is `None`, connections go to the server directly.
+    pub proxy: Option<Proxy>,
+
+    /// How to space out retries over UDP.
+    ///
+    /// If this is `None`, a request is resent as soon as it timed out.
+    pub backoff: Option<Backoff>,
+}
+
+impl Server {
+    /// Returns the local address to bind sockets for this server to.
+    ///
+    /// Fails if an explicitly given source address is of a different
+    /// address family than the server address.
+    pub fn bind_addr(&self) -> Result<SocketAddr, Error> {
+        match self.source {
+            Some(source) if source.is_ipv4() != self.addr.is_ipv4() => {
+                Err(Error::config(format!(
+                    "source address {} and server address {} are of \
+                     different address families",
+                    source.ip(),
+                    self.addr.ip()
+                )))
+            }
+            Some(source) => Ok(source),
+            None if self.addr.is_ipv4() => {
+                Ok(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+            }
+            None => Ok(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))),
+        }
+    }
+
+    /// Opens a TCP connection to the server.
+    ///
+    /// The connection goes through the proxy if there is one. Otherwise,
+    /// the socket is bound to the source address if there is one.
+    pub async fn connect_tcp(&self) -> Result<TcpStream, Error> {
+        if let Some(proxy) = self.proxy.as_ref() {
+            return proxy.connect(self.addr).await;
+        }
+        let Some(source) = self.source else {
+            return Ok(TcpStream::connect(self.addr).await?);
+        };
+        let bind_addr = self.bind_addr()?;
+        let socket = if source.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        socket.bind(bind_addr)?;
+        Ok(socket.connect(self.addr).await?)
+    }
+}
+
+//------------ Proxy ---------------------------------------------------------
+
+/// A SOCKS5 proxy to reach servers through.
+///
+/// Only the CONNECT command without authentication is supported, so the
+/// proxy can only be used for transports running over TCP.
+#[derive(Clone, Debug)]
+pub struct Proxy {
+    /// The host name or IP address of the proxy.
+    host: String,
+
+    /// The port of the proxy.
+    port: u16,
+}
+
+impl Proxy {
+    /// Opens a TCP connection to `addr` through the proxy.
+    ///
+    /// This follows RFC 1928.
+    async fn connect(&self, addr: SocketAddr) -> Result<TcpStream, Error> {
+        debug!("connecting to {} via proxy {}", addr, self);
+        let mut socket = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map_err(|err| {
+                Error::transport(format!(
+                    "cannot connect to proxy {}: {}",
+                    self, err
+                ))
+            })?;
+
+        // Offer “no authentication required” as the only method.
+        socket.write_all(&[5, 1, 0]).await?;
+        let mut reply = [0; 2];
+        socket.read_exact(&mut reply).await?;
+        match reply {
+            [5, 0] => {}
+            [5, _] => {
+                return Err(Error::transport(format!(
+                    "proxy {} requires authentication",
+                    self
+                )))
+            }
+            _ => {
+                return Err(Error::transport(format!(
+                    "{} is not a SOCKS5 proxy",
+                    self
+                )))
+            }
+        }
+
+        let mut request = vec![5, 1, 0];
+        match addr {
+            SocketAddr::V4(addr) => {
+                request.push(1);
+                request.extend_from_slice(&addr.ip().octets());
+            }
+            SocketAddr::V6(addr) => {
+                request.push(4);
+                request.extend_from_slice(&addr.ip().octets());
+            }
+        }
+        request.extend_from_slice(&addr.port().to_be_bytes());
+        socket.write_all(&request).await?;
+
+        let mut reply = [0; 4];
+        socket.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(Error::transport(format!(
+                "proxy {} cannot connect to {}: {}",
+                self,
+                addr,
+                Self::reply_text(reply[1])
+            )));
+        }
+
+        // Skip the address the proxy bound to, followed by its port.
+        let len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0; 1];
+                socket.read_exact(&mut len).await?;
+                usize::from(len[0])
+            }
+            _ => {
+                return Err(Error::transport(format!(
+                    "invalid reply from proxy {}",
+                    self
+                )))
+            }
+        };
+        let mut bound = vec![0; len + 2];
+        socket.read_exact(&mut bound).await?;
+        Ok(socket)
+    }
+
+    /// Returns a description of a SOCKS5 reply code.
+    fn reply_text(code: u8) -> &'static str {
+        match code {
+            1 => "general failure",
+            2 => "connection not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            7 => "command not supported",
+            8 => "address type not supported",
+            _ => "unknown error",
+        }
+    }
+}
+
+impl FromStr for Proxy {
+    type Err = &'static str;
+
+    /// Parses a proxy URL of the form `socks5://host:port`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("socks5://")
+            .ok_or("expected a URL of the form socks5://host:port")?;
+        let (host, port) =
+            rest.rsplit_once(':').ok_or("missing port in proxy URL")?;
+        let port = port.parse().map_err(|_| "invalid port in proxy URL")?;
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        if host.is_empty() {
+            return Err("missing host in proxy URL");
+        }
+        Ok(Proxy {
+            host: host.into(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "socks5://[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "socks5://{}:{}", self.host, self.port)
+        }
+    }
+}
+
+//------------ Backoff -------------------------------------------------------
+
+/// How the time between retries over UDP grows.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    /// The additional time to wait for a response before the first retry.
+    pub delay: Duration,
+
+    /// The factor the delay grows by with every retry.
+    pub factor: f32,
+
+    /// Whether to randomize the delay to avoid synchronized retries.
+    pub jitter: bool,
+}
+
+impl Backoff {
+    /// Returns the delay before the given retry, starting at zero.
+    ///
+    /// With jitter, the delay is picked at random between half and the
+    /// full delay. The delay never exceeds [`MAX_BACKOFF`].
+    pub fn delay(&self, retry: u8) -> Duration {
+        let delay = Duration::try_from_secs_f64(
+            self.delay.as_secs_f64()
+                * f64::from(self.factor).powi(i32::from(retry)),
+        )
+        .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF));
+        if self.jitter {
+            delay.mul_f64(0.5 + random_fraction() / 2.0)
+        } else {
+            delay
+        }
+    }
+}
+
+/// The longest delay between two retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Returns a random number between zero and one.
+fn random_fraction() -> f64 {
+    (random_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Returns a random 64 bit number.
+///
+/// The standard library doesn’t have a random number generator but its
+/// hash maps are randomly seeded. This isn’t suitable for cryptography
+/// but good enough for jitter or mixing up the case of names.
+pub fn random_u64() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    RandomState::new().build_hasher().finish()
 }
 
 //------------ Transport -----------------------------------------------------
@@ -215,6 +1634,23 @@ pub enum Transport {
     UdpTcp,
     Tcp,
     Tls,
+    OpportunisticTls,
+    Https,
+    Quic,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Transport::Udp => "UDP",
+            Transport::UdpTcp => "UDP with TCP fallback",
+            Transport::Tcp => "TCP",
+            Transport::Tls => "TLS",
+            Transport::OpportunisticTls => "TLS with UDP/TCP fallback",
+            Transport::Https => "HTTPS",
+            Transport::Quic => "QUIC",
+        })
+    }
 }
 
 impl From<conf::Transport> for Transport {
@@ -229,9 +1665,12 @@ impl From<conf::Transport> for Transport {
 //------------ Answer --------------------------------------------------------
 
 /// An answer for a query.
+#[derive(Clone)]
 pub struct Answer {
     message: Message<Bytes>,
     stats: Stats,
+    tls: Option<TlsInfo>,
+    server_name: Option<String>,
 }
 
 impl Answer {
@@ -239,6 +1678,21 @@ impl Answer {
         self.stats
     }
 
+    /// Returns details of the TLS session if the answer was received via TLS.
+    pub fn tls(&self) -> Option<&TlsInfo> {
+        self.tls.as_ref()
+    }
+
+    /// Returns the host name of the server if it has been resolved.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Sets the host name of the server the answer was received from.
+    pub fn set_server_name(&mut self, name: Option<String>) {
+        self.server_name = name;
+    }
+
     pub fn message(&self) -> &Message<Bytes> {
         &self.message
     }
@@ -262,6 +1716,21 @@ pub struct Stats {
     pub duration: TimeDelta,
     pub server_addr: SocketAddr,
     pub server_proto: Protocol,
+
+    /// The time it took to establish the TCP connection.
+    ///
+    /// This is `None` if no new connection was established.
+    pub connect_time: Option<TimeDelta>,
+
+    /// The time it took to complete the TLS handshake.
+    ///
+    /// This is `None` if there was no TLS handshake.
+    pub tls_time: Option<TimeDelta>,
+
+    /// The number of times the request was sent over UDP.
+    ///
+    /// This is `None` if the number isn’t known.
+    pub attempts: Option<u8>,
 }
 
 impl Stats {
@@ -271,14 +1740,67 @@ impl Stats {
             duration: Default::default(),
             server_addr,
             server_proto,
+            connect_time: None,
+            tls_time: None,
+            attempts: None,
         }
     }
 
+    /// Marks the TCP connection as established.
+    fn connected(&mut self) {
+        self.connect_time = Some(Local::now() - self.start);
+    }
+
+    /// Marks the TLS handshake as completed.
+    fn tls_established(&mut self) {
+        self.tls_time = Some(
+            Local::now() - self.start - self.connect_time.unwrap_or_default(),
+        );
+    }
+
     fn finalize(&mut self) {
         self.duration = Local::now() - self.start;
     }
 }
 
+//------------ TlsInfo -------------------------------------------------------
+
+/// Details about the TLS session an answer was received over.
+#[derive(Clone, Debug)]
+pub struct TlsInfo {
+    /// The name of the server used for SNI and certificate verification.
+    pub server_name: String,
+
+    /// The negotiated TLS protocol version.
+    pub version: Option<&'static str>,
+
+    /// The negotiated cipher suite if it is known.
+    pub cipher_suite: Option<String>,
+
+    /// The negotiated ALPN protocol, if any.
+    pub alpn: Option<Vec<u8>>,
+}
+
+impl TlsInfo {
+    fn new(
+        server_name: String,
+        conn: &tokio_rustls::rustls::ClientConnection,
+    ) -> Self {
+        TlsInfo {
+            server_name,
+            version: conn.protocol_version().map(|version| match version {
+                ProtocolVersion::TLSv1_2 => "TLSv1.2",
+                ProtocolVersion::TLSv1_3 => "TLSv1.3",
+                _ => "unknown",
+            }),
+            cipher_suite: conn
+                .negotiated_cipher_suite()
+                .map(|suite| format!("{:?}", suite.suite())),
+            alpn: conn.alpn_protocol().map(Vec::from),
+        }
+    }
+}
+
 //------------ Protocol ------------------------------------------------------
 
 #[derive(Clone, Copy, Debug)]
@@ -286,6 +1808,9 @@ pub enum Protocol {
     Udp,
     Tcp,
     Tls,
+    Https,
+    Quic,
+    Replay,
 }
 
 impl fmt::Display for Protocol {
@@ -294,6 +1819,90 @@ impl fmt::Display for Protocol {
             Protocol::Udp => "UDP",
             Protocol::Tcp => "TCP",
             Protocol::Tls => "TLS",
+            Protocol::Https => "HTTPS",
+            Protocol::Quic => "QUIC",
+            Protocol::Replay => "REPLAY",
         })
     }
 }
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEADER: &str = "HTTP/1.1 200 OK\r\n\
+                          Content-Type: application/dns-message\r\n";
+
+    #[test]
+    fn quic_length_prefix() {
+        let msg = MessageBuilder::new_vec().into_message();
+        let mut data = u16::try_from(msg.as_slice().len())
+            .unwrap()
+            .to_be_bytes()
+            .to_vec();
+        data.extend_from_slice(msg.as_slice());
+        assert!(quic_response_message(data.clone()).is_ok());
+
+        let mut long = data.clone();
+        long.push(0);
+        assert!(quic_response_message(long).is_err());
+        let short = data[..data.len() - 1].to_vec();
+        assert!(quic_response_message(short).is_err());
+        assert!(quic_response_message(vec![0]).is_err());
+    }
+
+    #[test]
+    fn http_content_length() {
+        let data = format!("{}Content-Length: 4\r\n\r\nabcd", HEADER);
+        let data = data.as_bytes();
+
+        // The body is complete without the connection being closed.
+        let body = http_response_body(data, false).unwrap();
+        assert_eq!(body.as_deref(), Some(b"abcd".as_ref()));
+
+        // A short body needs more data or is an error at the end.
+        let short = &data[..data.len() - 1];
+        assert!(http_response_body(short, false).unwrap().is_none());
+        assert!(http_response_body(short, true).is_err());
+    }
+
+    #[test]
+    fn http_chunked() {
+        let data = format!(
+            "{}Transfer-Encoding: chunked\r\n\r\n\
+             2\r\nab\r\n2;ext=1\r\ncd\r\n0\r\n\r\n",
+            HEADER
+        );
+        let data = data.as_bytes();
+        let body = http_response_body(data, false).unwrap();
+        assert_eq!(body.as_deref(), Some(b"abcd".as_ref()));
+
+        let short = &data[..data.len() - 5];
+        assert!(http_response_body(short, false).unwrap().is_none());
+        assert!(http_response_body(short, true).is_err());
+    }
+
+    #[test]
+    fn http_bad_response() {
+        let status = "HTTP/1.1 404 Not Found\r\n\
+                      Content-Type: application/dns-message\r\n\
+                      Content-Length: 0\r\n\r\n";
+        assert!(http_response_body(status.as_bytes(), false).is_err());
+
+        let html = "HTTP/1.1 200 OK\r\n\
+                    Content-Type: text/html\r\n\
+                    Content-Length: 0\r\n\r\n";
+        assert!(http_response_body(html.as_bytes(), false).is_err());
+
+        let missing = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        assert!(http_response_body(missing.as_bytes(), false).is_err());
+
+        let params = "HTTP/1.1 200 OK\r\n\
+                      Content-Type: Application/DNS-Message; x=y\r\n\
+                      Content-Length: 0\r\n\r\n";
+        let body = http_response_body(params.as_bytes(), false).unwrap();
+        assert_eq!(body.as_deref(), Some(b"".as_ref()));
+    }
+}
@@ -19,6 +19,8 @@ impl Help {
         let page = match self.command.as_deref() {
             None => Self::DNSI_1,
             Some("query") => Self::DNSI_QUERY_1,
+            Some("soa-check") => Self::DNSI_SOA_CHECK_1,
+            Some("validate") => Self::DNSI_VALIDATE_1,
             Some(command) => {
                 return Err(format!("Unknown command '{}'.", command).into());
             }
@@ -50,4 +52,8 @@ impl Help {
     const DNSI_1: &'static [u8] = include_bytes!("../../doc/dnsi.1");
     const DNSI_QUERY_1: &'static [u8] =
         include_bytes!("../../doc/dnsi-query.1");
+    const DNSI_SOA_CHECK_1: &'static [u8] =
+        include_bytes!("../../doc/dnsi-soa-check.1");
+    const DNSI_VALIDATE_1: &'static [u8] =
+        include_bytes!("../../doc/dnsi-validate.1");
 }
@@ -1,10 +1,16 @@
 //! The lookup command of _dnsi._
 
 use crate::error::Error;
+use crate::output::{OutputFormat, OutputOptions, Report};
 use domain::base::name::UncertainName;
+use domain::resolv::stub::conf::{ResolvConf, ServerConf, Transport};
 use domain::resolv::stub::StubResolver;
-use std::net::IpAddr;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
+use std::sync::Arc;
 
 //------------ Lookup --------------------------------------------------------
 
@@ -13,6 +19,23 @@ pub struct Lookup {
     /// The host or address to look up.
     #[arg(value_name = "HOST_OR_ADDR")]
     names: Vec<ServerName>,
+
+    /// The server to send queries to. System servers used if missing.
+    #[arg(short, long, value_name = "ADDR_OR_HOST")]
+    server: Option<ServerName>,
+
+    /// The port of the server to send queries to.
+    #[arg(short = 'p', long = "port", requires = "server")]
+    port: Option<u16>,
+
+    /// Print this line between the results for different names.
+    ///
+    /// Without it, an empty line is printed except with JSON.
+    #[arg(long, value_name = "STRING")]
+    separator: Option<String>,
+
+    #[command(flatten)]
+    output: OutputOptions,
 }
 
 /// # Executing the command
@@ -27,46 +50,117 @@ impl Lookup {
     }
 
     pub async fn async_execute(self) -> Result<(), Error> {
-        let resolver = StubResolver::new();
+        let resolver = Arc::new(self.resolver().await?);
 
+        // Lookups run concurrently but their results are printed in the
+        // order the names were given in.
         let mut res = Ok(());
-        let mut names = self.names.iter();
+        let mut names = self.names.iter().cloned();
+        let mut pending = VecDeque::new();
+        let mut first = true;
 
-        if let Some(name) = names.next() {
-            res = res.and(self.execute_one_name(&resolver, name).await);
-        }
+        loop {
+            while pending.len() < MAX_CONCURRENT_LOOKUPS {
+                let Some(name) = names.next() else { break };
+                let resolver = resolver.clone();
+                pending.push_back(tokio::spawn(async move {
+                    lookup_one_name(&resolver, &name).await
+                }));
+            }
+
+            let Some(lookup) = pending.pop_front() else {
+                break;
+            };
+            if let Some(separator) = self.separator().filter(|_| !first) {
+                println!("{}", separator);
+            }
+            first = false;
 
-        for name in names {
-            println!();
-            res = res.and(self.execute_one_name(&resolver, name).await);
+            match lookup.await {
+                Ok(Ok(output)) => self.output.print_report(&output)?,
+                Ok(Err(err)) => {
+                    eprintln!("{err}");
+                    res = Err(());
+                }
+                Err(err) => {
+                    eprintln!("{err}");
+                    res = Err(());
+                }
+            }
         }
 
         res.map_err(|_| "not all lookups have succeeded".into())
     }
+}
 
-    async fn execute_one_name(
-        &self,
-        resolver: &StubResolver,
-        name: &ServerName,
-    ) -> Result<(), ()> {
-        let res = match name {
-            ServerName::Name(host) => forward(resolver, host).await,
-            ServerName::Addr(addr) => reverse(resolver, *addr).await,
-        };
+/// # Configuration
+///
+impl Lookup {
+    /// Returns the line to print between the results for two names.
+    ///
+    /// By default, text results are separated by an empty line while JSON
+    /// results aren’t separated so there is exactly one object per line.
+    fn separator(&self) -> Option<&str> {
+        match self.separator.as_deref() {
+            Some(separator) => Some(separator),
+            None if matches!(self.output.format, OutputFormat::Json) => None,
+            None => Some(""),
+        }
+    }
 
-        if let Err(err) = res {
-            eprintln!("{err}");
-            return Err(());
+    /// Creates the resolver for the given server or the system servers.
+    async fn resolver(&self) -> Result<StubResolver, Error> {
+        let addrs = match self.server.as_ref() {
+            None => return Ok(StubResolver::new()),
+            Some(ServerName::Addr(addr)) => vec![*addr],
+            Some(ServerName::Name(host)) => {
+                let resolver = StubResolver::new();
+                let answer = match host {
+                    UncertainName::Absolute(name) => {
+                        resolver.lookup_host(name).await?
+                    }
+                    UncertainName::Relative(name) => {
+                        resolver.search_host(name).await?
+                    }
+                };
+                answer.iter().collect()
+            }
+        };
+        if addrs.is_empty() {
+            return Err("no addresses found for the server".into());
         }
+        let mut conf = ResolvConf::new();
+        conf.servers = addrs
+            .into_iter()
+            .map(|addr| {
+                ServerConf::new(
+                    SocketAddr::new(addr, self.port.unwrap_or(53)),
+                    Transport::UdpTcp,
+                )
+            })
+            .collect();
+        conf.finalize();
+        Ok(StubResolver::from_conf(conf))
+    }
+}
 
-        Ok(())
+/// The maximum number of lookups in flight at the same time.
+const MAX_CONCURRENT_LOOKUPS: usize = 16;
+
+async fn lookup_one_name(
+    resolver: &StubResolver,
+    name: &ServerName,
+) -> Result<LookupResult, Error> {
+    match name {
+        ServerName::Name(host) => forward(resolver, host).await,
+        ServerName::Addr(addr) => reverse(resolver, *addr).await,
     }
 }
 
 async fn forward(
     resolver: &StubResolver,
     name: &UncertainName<Vec<u8>>,
-) -> Result<(), Error> {
+) -> Result<LookupResult, Error> {
     let answer = match name {
         UncertainName::Absolute(ref name) => {
             resolver.lookup_host(name).await?
@@ -76,41 +170,101 @@ async fn forward(
         }
     };
 
-    print!("{name}");
-
     let canon = answer.canonical_name();
-    if canon != answer.qname() {
-        print!(" (alias for {canon})");
-    }
+    Ok(LookupResult::Forward {
+        name: name.to_string(),
+        alias_for: (canon != answer.qname()).then(|| canon.to_string()),
+        addrs: answer.iter().collect(),
+    })
+}
 
-    println!();
+async fn reverse(
+    resolver: &StubResolver,
+    addr: IpAddr,
+) -> Result<LookupResult, Error> {
+    let answer = resolver.lookup_addr(addr).await?;
+    Ok(LookupResult::Reverse {
+        addr,
+        hosts: answer.iter().map(|name| name.to_string()).collect(),
+    })
+}
 
-    let addrs: Vec<_> = answer.iter().collect();
-    if addrs.is_empty() {
-        println!("  <no addresses found>");
-    } else {
-        for addr in addrs {
-            println!("  {addr}");
-        }
-    }
+//------------ LookupResult --------------------------------------------------
 
-    Ok(())
+/// The result of looking up a single host or address.
+///
+/// In JSON, the result is an object with the name as given, the canonical
+/// name if it differs, and the addresses for a host or with the address
+/// and its host names for an address.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+enum LookupResult {
+    Forward {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        alias_for: Option<String>,
+        addrs: Vec<IpAddr>,
+    },
+    Reverse {
+        addr: IpAddr,
+        hosts: Vec<String>,
+    },
 }
 
-async fn reverse(resolver: &StubResolver, addr: IpAddr) -> Result<(), Error> {
-    let answer = resolver.lookup_addr(addr).await?;
-    println!("{addr}");
-
-    let hosts: Vec<_> = answer.iter().collect();
-    if hosts.is_empty() {
-        println!("  <no hosts found>");
-    } else {
-        for name in hosts {
-            println!("  {name}");
+impl Report for LookupResult {
+    fn write_text(
+        &self,
+        target: &mut dyn io::Write,
+    ) -> Result<(), io::Error> {
+        match self {
+            Self::Forward {
+                name,
+                alias_for,
+                addrs,
+            } => {
+                write!(target, "{name}")?;
+                if let Some(canon) = alias_for {
+                    write!(target, " (alias for {canon})")?;
+                }
+                writeln!(target)?;
+                if addrs.is_empty() {
+                    writeln!(target, "  <no addresses found>")?;
+                }
+                for addr in addrs {
+                    writeln!(target, "  {addr}")?;
+                }
+            }
+            Self::Reverse { addr, hosts } => {
+                writeln!(target, "{addr}")?;
+                if hosts.is_empty() {
+                    writeln!(target, "  <no hosts found>")?;
+                }
+                for name in hosts {
+                    writeln!(target, "  {name}")?;
+                }
+            }
         }
+        Ok(())
     }
 
-    Ok(())
+    fn write_short(
+        &self,
+        target: &mut dyn io::Write,
+    ) -> Result<(), io::Error> {
+        match self {
+            Self::Forward { addrs, .. } => {
+                for addr in addrs {
+                    writeln!(target, "{addr}")?;
+                }
+            }
+            Self::Reverse { hosts, .. } => {
+                for name in hosts {
+                    writeln!(target, "{name}")?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 //------------ ServerName ---------------------------------------------------
@@ -134,3 +288,57 @@ impl FromStr for ServerName {
         }
     }
 }
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn separator() {
+        #[derive(clap::Parser)]
+        struct Cmd {
+            #[command(flatten)]
+            lookup: Lookup,
+        }
+
+        let separator = |args: &[&str]| {
+            let args = ["dnsi", "example.com"].iter().chain(args);
+            Cmd::parse_from(args).lookup.separator().map(String::from)
+        };
+        assert_eq!(separator(&[]).as_deref(), Some(""));
+        assert_eq!(separator(&["--format", "json"]), None);
+        assert_eq!(
+            separator(&["--format", "json", "--separator", "%%"]).as_deref(),
+            Some("%%")
+        );
+        assert_eq!(separator(&["--separator", "%%"]).as_deref(), Some("%%"));
+    }
+
+    #[test]
+    fn json_result() {
+        let forward = LookupResult::Forward {
+            name: "www.example.com".into(),
+            alias_for: Some("example.com.".into()),
+            addrs: vec!["192.0.2.1".parse().unwrap()],
+        };
+        assert_eq!(
+            serde_json::to_string(&forward).unwrap(),
+            concat!(
+                r#"{"name":"www.example.com","alias_for":"example.com.","#,
+                r#""addrs":["192.0.2.1"]}"#
+            )
+        );
+
+        let reverse = LookupResult::Reverse {
+            addr: "2001:db8::1".parse().unwrap(),
+            hosts: vec!["host\"name".into()],
+        };
+        assert_eq!(
+            serde_json::to_string(&reverse).unwrap(),
+            r#"{"addr":"2001:db8::1","hosts":["host\"name"]}"#
+        );
+    }
+}
@@ -3,6 +3,8 @@
 pub mod help;
 pub mod lookup;
 pub mod query;
+pub mod soa_check;
+pub mod validate;
 
 use super::error::Error;
 
@@ -14,6 +16,12 @@ pub enum Command {
     /// Lookup a host or address.
     Lookup(self::lookup::Lookup),
 
+    /// Compare the SOA serial of all authoritative servers of a zone.
+    SoaCheck(self::soa_check::SoaCheck),
+
+    /// Validate the DNSSEC signatures of records.
+    Validate(self::validate::Validate),
+
     /// Show the manual pages.
     Help(self::help::Help),
 }
@@ -23,6 +31,8 @@ impl Command {
         match self {
             Self::Query(query) => query.execute(),
             Self::Lookup(lookup) => lookup.execute(),
+            Self::SoaCheck(soa_check) => soa_check.execute(),
+            Self::Validate(validate) => validate.execute(),
             Self::Help(help) => help.execute(),
         }
     }
@@ -1,42 +1,141 @@
 //! The query command of _dnsi._
 
-use crate::client::{Answer, Client, Server, Transport};
+use crate::client::{
+    random_u64, Answer, Backoff, Client, Protocol, Proxy, Recording, Server,
+    Transport,
+};
 use crate::error::Error;
-use crate::output::OutputOptions;
+use crate::output::{CompressionStats, OutputFormat, OutputOptions};
 use bytes::Bytes;
-use domain::base::iana::{Class, Rtype};
-use domain::base::message::Message;
+use chrono::TimeDelta;
+use domain::base::iana::{Class, Opcode, OptionCode, Rcode, Rtype};
+use domain::base::message::{Message, RecordSection};
 use domain::base::message_builder::MessageBuilder;
 use domain::base::name::{Name, ParsedName, ToName, UncertainName};
+use domain::base::opt::{AllOptData, ClientSubnet, UnknownOptData};
 use domain::base::rdata::RecordData;
+use domain::base::Ttl;
 use domain::net::client::request::{ComposeRequest, RequestMessage};
-use domain::rdata::{AllRecordData, Ns, Soa};
+use domain::rdata::{AllRecordData, Cname, Ns, Soa};
 use domain::resolv::stub::conf::ResolvConf;
 use domain::resolv::stub::StubResolver;
-use std::collections::HashSet;
+use domain::tsig;
+use domain::utils::base64;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
-use std::net::{IpAddr, SocketAddr};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 //------------ Query ---------------------------------------------------------
 
 #[derive(Clone, Debug, clap::Args)]
+#[command(group(clap::ArgGroup::new("servers").multiple(true)))]
 pub struct Query {
     /// The name of the resource records to look up
-    #[arg(value_name = "QUERY_NAME_OR_ADDR")]
-    qname: NameOrAddr,
+    #[arg(
+        value_name = "QUERY_NAME_OR_ADDR",
+        required_unless_present_any = ["reverse", "interactive"]
+    )]
+    qname: Option<NameOrAddr>,
+
+    /// Look up the PTR record for this address like dig -x does.
+    #[arg(
+        short = 'x',
+        long,
+        value_name = "ADDR",
+        conflicts_with_all = ["qname", "qtype"]
+    )]
+    reverse: Option<IpAddr>,
+
+    /// Reject query names that aren’t valid host names.
+    #[arg(long)]
+    strict_name: bool,
+
+    /// Read NAME [TYPE ...] lines from stdin and send a query for each.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "qname", "reverse", "qtype", "repeat", "wait", "replay"
+        ]
+    )]
+    interactive: bool,
+
+    /// Randomize the case of the query name and check the response.
+    #[arg(long = "0x20")]
+    case_randomization: bool,
+
+    /// The seed for randomizing the case of the query name.
+    ///
+    /// It is picked once, so all requests use the same query name.
+    #[arg(skip)]
+    case_seed: u64,
+
+    /// The record types to look up, each in a separate query
+    #[arg(value_name = "QUERY_TYPE", value_parser = parse_rtype)]
+    qtype: Vec<Rtype>,
+
+    /// The class of the query.
+    #[arg(
+        short,
+        long,
+        value_name = "CLASS",
+        default_value = "IN",
+        value_parser = parse_class
+    )]
+    class: Class,
+
+    // DNSI_SERVER is read by `server_specs` rather than by clap so that it
+    // doesn’t count as a server given on the command line.
+    /// The servers to try in order. DNSI_SERVER or system servers if missing
+    #[arg(
+        short,
+        long,
+        value_name = "ADDR_OR_HOST[:PORT]",
+        group = "servers"
+    )]
+    server: Vec<ServerSpec>,
+
+    /// The server given dig-style as @server.
+    ///
+    /// Arguments starting with @ are turned into this option before the
+    /// command line is parsed.
+    #[arg(
+        long,
+        value_name = "ADDR_OR_HOST[:PORT]",
+        hide = true,
+        group = "servers"
+    )]
+    at_server: Option<ServerSpec>,
+
+    /// Show the host name of the server’s address in the statistics.
+    #[arg(long)]
+    resolve_server_name: bool,
+
+    /// Print the server addresses and transport before sending the query.
+    #[arg(long)]
+    show_server: bool,
+
+    /// Race the servers against each other instead of trying them in order.
+    #[arg(long)]
+    race: bool,
 
-    /// The record type to look up
-    #[arg(value_name = "QUERY_TYPE")]
-    qtype: Option<Rtype>,
+    /// The local address and port to send the query from.
+    #[arg(long, value_name = "ADDR[:PORT]", value_parser = parse_source)]
+    source: Option<SocketAddr>,
 
-    /// The server to send the query to. System servers used if missing
-    #[arg(short, long, value_name = "ADDR_OR_HOST")]
-    server: Option<ServerName>,
+    /// Connect to the server through this SOCKS5 proxy.
+    #[arg(long, value_name = "URL", conflicts_with = "source")]
+    proxy: Option<Proxy>,
 
     /// The port of the server to send query to.
-    #[arg(short = 'p', long = "port", requires = "server")]
+    #[arg(
+        short = 'p',
+        long = "port",
+        value_parser = parse_port
+    )]
     port: Option<u16>,
 
     /// Use only IPv4 for communication.
@@ -59,10 +158,49 @@ pub struct Query {
     #[arg(long)]
     tls: bool,
 
+    /// Use DNS over HTTPS.
+    #[arg(long, conflicts_with_all = ["tls", "tcp", "udp"])]
+    https: bool,
+
+    /// Use DNS over QUIC.
+    #[arg(long, conflicts_with_all = ["tls", "tcp", "udp", "https"])]
+    quic: bool,
+
+    /// The path of the URL for DNS over HTTPS.
+    #[arg(long, value_name = "PATH", default_value = "/dns-query")]
+    http_path: String,
+
+    /// Try TLS first and fall back to UDP and TCP if that fails.
+    #[arg(
+        long,
+        conflicts_with_all = ["tls", "tcp", "udp", "https", "quic"]
+    )]
+    opportunistic_tls: bool,
+
+    /// The transport to use unless given via --udp, --tcp, or --tls.
+    #[arg(long, value_name = "TRANSPORT", env = "DNSI_TRANSPORT")]
+    transport: Option<TransportArg>,
+
     /// The name of the server for SNI and certificate verification.
-    #[arg(long = "tls-hostname")]
+    #[arg(long = "tls-hostname", env = "DNSI_TLS_HOSTNAME")]
     tls_hostname: Option<String>,
 
+    /// Do not verify the TLS certificate of the server.
+    #[arg(long)]
+    insecure: bool,
+
+    /// Send the query over this already connected TCP socket.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        value_name = "FD",
+        value_parser = clap::value_parser!(i32).range(0..),
+        conflicts_with_all = [
+            "servers", "udp", "https", "quic", "record", "replay"
+        ]
+    )]
+    fd: Option<std::os::fd::RawFd>,
+
     /// Set the timeout for a query.
     #[arg(long, value_name = "SECONDS")]
     timeout: Option<f32>,
@@ -71,10 +209,32 @@ pub struct Query {
     #[arg(long)]
     retries: Option<u8>,
 
-    /// Set the advertised UDP payload size.
+    /// Wait this many more milliseconds for a response before retrying.
+    #[arg(long, value_name = "MS")]
+    retry_backoff: Option<u64>,
+
+    /// The factor the backoff grows by with every retry.
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value = "2",
+        value_parser = parse_backoff_factor,
+        requires = "retry_backoff"
+    )]
+    backoff_factor: f32,
+
+    /// Randomize the backoff to avoid synchronized retries.
+    #[arg(long, requires = "retry_backoff")]
+    jitter: bool,
+
+    /// Set the advertised UDP payload size. Must be at least 512.
     #[arg(long)]
     udp_payload_size: Option<u16>,
 
+    /// Use the UDP payload size from resolv.conf for explicit servers, too.
+    #[arg(long, conflicts_with = "udp_payload_size")]
+    edns_bufsize_auto: bool,
+
     // No need to set the AA flag in the request.
     /// Set the AD flag in the request.
     #[arg(long, overrides_with = "_no_ad")]
@@ -111,18 +271,227 @@ pub struct Query {
     _rd: bool,
 
     /// Do not set the RD flag in the request.
-    #[arg(long = "no-rd")]
+    #[arg(long = "no-rd", visible_alias = "norecurse")]
     no_rd: bool,
 
+    /// The opcode of the request.
+    #[arg(
+        long,
+        value_name = "OPCODE",
+        default_value = "QUERY",
+        value_parser = parse_opcode
+    )]
+    opcode: Opcode,
+
+    /// Set the reserved Z bit in the request to this value.
+    #[arg(long, value_name = "HEX", value_parser = parse_z_bits)]
+    z_bits: Option<u8>,
+
+    /// Use this message ID instead of a random one.
+    #[arg(long)]
+    id: Option<u16>,
+
     // No need to set the TC flag in the request.
     /// Disable all sanity checks.
     #[arg(long, short = 'f')]
     force: bool,
 
+    /// Enable all options that are unsafe outside of a test lab.
+    #[arg(long)]
+    insecure_everything: bool,
+
     /// Verify the answer against an authoritative server.
     #[arg(long)]
     verify: bool,
 
+    /// Also compare the authority and additional sections when verifying.
+    #[arg(long, requires = "verify")]
+    verify_sections: bool,
+
+    /// Report records that only differ in their TTL when verifying.
+    #[arg(long, requires = "verify")]
+    verify_ttl: bool,
+
+    /// Send the query over UDP, TCP, and, if possible, TLS and compare.
+    #[arg(long, conflicts_with = "replay")]
+    compare_transports: bool,
+
+    /// Require a record of the query type with this data in the answer.
+    #[arg(long, value_name = "RDATA")]
+    expect: Option<String>,
+
+    /// Repeat the query until the answer contains the expected data.
+    #[arg(long, requires = "expect", conflicts_with = "replay")]
+    wait: bool,
+
+    /// The number of seconds between queries when waiting.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "5",
+        requires = "wait"
+    )]
+    interval: u64,
+
+    /// The maximum number of seconds to wait for the expected data.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "600",
+        requires = "wait"
+    )]
+    wait_timeout: u64,
+
+    /// Send the query this many times and print latency statistics.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["wait", "trace", "replay"]
+    )]
+    repeat: Option<u32>,
+
+    /// The number of queries in flight at the same time when repeating.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "repeat"
+    )]
+    concurrency: u32,
+
+    /// Save the response into a file in this directory.
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Use a response saved via --record instead of sending a query.
+    #[arg(long, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    /// Sign the request with this TSIG key and verify the response.
+    #[arg(long, value_name = "NAME:ALGORITHM:SECRET")]
+    tsig: Option<TsigSpec>,
+
+    /// Exit successfully even if the response has an error rcode.
+    #[arg(long)]
+    no_rcode_exit: bool,
+
+    /// Request the server’s name server identifier (NSID).
+    #[arg(long)]
+    nsid: bool,
+
+    /// Request the NSID and fail unless it is this hex value.
+    #[arg(long, value_name = "HEX")]
+    expect_nsid: Option<HexData>,
+
+    /// Send this client cookie even if it is malformed.
+    #[arg(long, value_name = "HEX")]
+    cookie_raw: Option<HexData>,
+
+    /// Signal these comma-separated trust anchor key tags to the server.
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    keytag: Vec<u16>,
+
+    /// Request a DNSSEC chain starting below this trust point.
+    #[arg(long, value_name = "NAME")]
+    chain: Option<Name<Vec<u8>>>,
+
+    /// Request the expire value of the zone from the server.
+    #[arg(long)]
+    expire: bool,
+
+    /// Signal these comma-separated DNSSEC algorithms as understood.
+    #[arg(
+        long,
+        value_name = "ALGS",
+        value_delimiter = ',',
+        value_parser = parse_algorithm
+    )]
+    dau: Vec<u8>,
+
+    /// Signal these comma-separated DS hash algorithms as understood.
+    #[arg(
+        long,
+        value_name = "ALGS",
+        value_delimiter = ',',
+        value_parser = parse_algorithm
+    )]
+    dhu: Vec<u8>,
+
+    /// Signal these comma-separated NSEC3 hash algorithms as understood.
+    #[arg(
+        long,
+        value_name = "ALGS",
+        value_delimiter = ',',
+        value_parser = parse_algorithm
+    )]
+    n3u: Vec<u8>,
+
+    /// Add an EDNS option with this numeric code and hex data. Repeatable.
+    #[arg(long, value_name = "CODE:HEX")]
+    ednsopt: Vec<RawOpt>,
+
+    /// Pad the request to a multiple of 128 octets or by LENGTH octets.
+    #[arg(long, value_name = "LENGTH", num_args = 0..=1)]
+    padding: Option<Option<u16>>,
+
+    /// Pad the request and check whether the response is padded, too.
+    #[arg(long)]
+    edns_padding_response_check: bool,
+
+    /// Look up the addresses of SRV, MX, and NS targets in the answer.
+    #[arg(long)]
+    resolve_targets: bool,
+
+    /// Print the parameters of the request after the response.
+    #[arg(long)]
+    echo_flags: bool,
+
+    /// Print a single line with timing information in logfmt style.
+    #[arg(long)]
+    emit_timing: bool,
+
+    /// Report how much of the response used name compression.
+    #[arg(long)]
+    compression_stats: bool,
+
+    /// Send an EDNS client subnet option for this prefix.
+    #[arg(long, value_name = "ADDR/LEN[/SCOPE]")]
+    subnet: Option<Subnet>,
+
+    /// Report the scope prefix of a client subnet option in the response.
+    #[arg(long)]
+    edns_subnet_scope: bool,
+
+    /// Print only the data of the first answer record of the query type.
+    #[arg(long, conflicts_with = "as_update_prereq")]
+    first: bool,
+
+    /// Print the answer as prerequisites for nsupdate.
+    #[arg(long)]
+    as_update_prereq: bool,
+
+    /// Print the codes of the options in the response’s OPT record.
+    #[arg(long)]
+    opt_codes: bool,
+
+    /// Follow referrals from the server to the servers of the child zone.
+    #[arg(long)]
+    follow_referrals: bool,
+
+    /// Print the request before sending it.
+    #[arg(long)]
+    show_request: bool,
+
+    /// Resolve the query iteratively starting at the root servers.
+    #[arg(long, conflicts_with_all = ["servers", "follow_referrals"])]
+    trace: bool,
+
+    /// Treat the server as a validating resolver on a trusted channel.
+    #[arg(long)]
+    trust_ad: bool,
+
     /// Output options.
     #[command(flatten)]
     output: OutputOptions,
@@ -131,11 +500,29 @@ pub struct Query {
 /// # Executing the command
 ///
 impl Query {
-    pub fn execute(self) -> Result<(), Error> {
-        #[allow(clippy::collapsible_if)] // There may be more later ...
+    pub fn execute(mut self) -> Result<(), Error> {
+        if self.insecure_everything {
+            eprintln!(
+                "WARNING: --insecure-everything is in effect.\n\
+                 WARNING: TLS certificates are not verified and all sanity \
+                 checks are disabled.\n\
+                 WARNING: Results MUST NOT be trusted. Use this in a test \
+                 lab only."
+            );
+            self.insecure = true;
+            self.force = true;
+        }
+        if self.strict_name && !self.interactive {
+            self.qname().check_hostname()?;
+        }
+        if self.case_randomization {
+            self.case_seed = random_u64();
+        }
         if !self.force {
-            let qtype = self.qtype();
-            if qtype == Rtype::AXFR || qtype == Rtype::IXFR {
+            if self
+                .qtypes()
+                .any(|qtype| qtype == Rtype::AXFR || qtype == Rtype::IXFR)
+            {
                 return Err(
                     "AXFR and IXFR query types invoke zone transfer which \
                      may result in a sequence\n\
@@ -146,6 +533,17 @@ impl Query {
                         .into(),
                 );
             }
+            if self.udp_payload_size() < MIN_UDP_PAYLOAD_SIZE {
+                return Err(format!(
+                    "Servers treat an advertised UDP payload size below {} \
+                     as {} (RFC 6891),\n\
+                     so a smaller value will not cause more truncation.\n\
+                     Use --tcp to force TCP.\n\
+                     (Use --force to query anyway.)",
+                    MIN_UDP_PAYLOAD_SIZE, MIN_UDP_PAYLOAD_SIZE,
+                )
+                .into());
+            }
         }
 
         tokio::runtime::Builder::new_multi_thread()
@@ -156,55 +554,184 @@ impl Query {
     }
 
     pub async fn async_execute(mut self) -> Result<(), Error> {
-        let client = match self.server {
-            Some(ServerName::Name(ref host)) => {
-                if self.tls_hostname.is_none() {
-                    self.tls_hostname = Some(host.to_string());
-                }
-                self.host_server(host).await?
+        let mut client = match self.recording() {
+            // Replaying doesn’t need any servers.
+            Some(Recording::Replay(_)) => Client::with_servers(Vec::new()),
+            // Neither does a socket passed in.
+            _ if self.has_fd() => Client::with_servers(Vec::new()),
+            _ => self.client().await?,
+        };
+        client.set_recording(self.recording());
+        client.set_insecure_tls(self.insecure);
+        client.set_http_path(self.http_path.clone());
+        client.set_keep_connections(true);
+        client.set_race(self.race);
+        if let Some(tsig) = self.tsig.as_ref() {
+            client.set_tsig_key(Some(tsig.key()?));
+        }
+        if self.show_server {
+            Self::output_servers(&client);
+        }
+        if self.interactive {
+            return self.interactive(&client).await;
+        }
+        self.execute_qtypes(&client).await
+    }
+
+    /// Sends the queries for all query types and prints the results.
+    async fn execute_qtypes(&self, client: &Client) -> Result<(), Error> {
+        if self.qtype.len() < 2 {
+            return self.execute_qtype(client).await;
+        }
+
+        // With several query types, we send one query for each and keep
+        // going if one of them fails. The first of the errors with the
+        // highest exit code is returned and thus printed by the caller.
+        // All other errors are printed here.
+        let mut res: Result<(), (Rtype, Error)> = Ok(());
+        for (i, qtype) in self.qtype.iter().enumerate() {
+            if i > 0 {
+                println!();
             }
-            Some(ServerName::Addr(addr)) => {
-                if self.tls && self.tls_hostname.is_none() {
-                    return Err(
-                        "--tls-hostname is required for TLS transport".into(),
-                    );
+            let mut query = self.clone();
+            query.qtype = vec![*qtype];
+            let Err(err) = query.execute_qtype(client).await else {
+                continue;
+            };
+            match res.as_ref() {
+                Err((_, worst)) if worst.exit_code() >= err.exit_code() => {
+                    eprintln!("{}: {}", qtype, err);
                 }
-                self.addr_server(addr)
-            }
-            None => {
-                if self.tls {
-                    return Err(
-                        "--server is required for TLS transport".into()
-                    );
+                Err((worst_qtype, worst)) => {
+                    eprintln!("{}: {}", worst_qtype, worst);
+                    res = Err((*qtype, err));
                 }
-                self.system_server()
+                Ok(()) => res = Err((*qtype, err)),
             }
-        };
+        }
+        res.map_err(|(_, err)| err)
+    }
 
-        let answer = client.request(self.create_request()).await?;
-        self.output.format.print(&answer)?;
+    /// Sends the query for a single query type and prints the result.
+    async fn execute_qtype(&self, client: &Client) -> Result<(), Error> {
+        if self.trace {
+            return self.trace().await;
+        }
+        if let Some(count) = self.repeat {
+            return self.benchmark(client, count).await;
+        }
+        if self.show_request {
+            self.output_request()?;
+        }
+        let mut answer = if self.wait {
+            self.wait_for_expected(client).await?
+        } else {
+            self.request(client).await?
+        };
+        if self.resolve_server_name {
+            answer.set_server_name(Self::lookup_server_name(&answer).await);
+        }
+        if self.first {
+            self.output_first(&answer)?;
+        } else if self.as_update_prereq {
+            Self::output_update_prereqs(&answer)?;
+        } else {
+            self.output.print(&answer)?;
+        }
+        if self.opportunistic_tls && self.human_output() {
+            Self::output_opportunistic_tls_note(&answer);
+        }
+        if self.human_output() && answer.message().header().z() {
+            println!("\n;; Reserved Z bit set in the response.");
+        }
+        if self.human_output() {
+            self.output_ad_note(&answer);
+            self.output_recursion_hint(&answer);
+        }
+        if self.case_randomization && self.human_output() {
+            self.output_case_check(&answer);
+        }
+        if self.human_output()
+            && matches!(self.transport(), Transport::Udp)
+            && answer.message().header().tc()
+        {
+            println!(
+                "\n;; Response truncated (TC flag set). \
+                 Repeat without --udp or with --tcp for the full response."
+            );
+        }
+        if self.edns_padding_response_check {
+            self.output_padding_check(&answer);
+        }
+        if self.compression_stats {
+            self.output_compression_stats(&answer);
+        }
+        if self.edns_subnet_scope {
+            Self::output_subnet_scope(&answer);
+        }
+        if self.opt_codes {
+            Self::output_opt_codes(&answer);
+        }
+        if self.resolve_targets {
+            self.output_targets(client, &answer).await?;
+        }
+        if self.follow_referrals {
+            self.follow_referrals(&self.qname().to_name(), &answer)
+                .await?;
+        }
+        if self.compare_transports {
+            self.output_transport_comparison(client).await?;
+        }
         if self.verify {
-            let auth_answer = self.auth_answer().await?;
-            if let Some(diff) =
-                Self::diff_answers(auth_answer.message(), answer.message())?
-            {
-                println!("\n;; Authoritative ANSWER does not match.");
-                println!(
-                    ";; Difference of ANSWER with authoritative server {}:",
-                    auth_answer.stats().server_addr
-                );
-                self.output_diff(diff);
-            } else {
-                println!("\n;; Authoritative ANSWER matches.");
+            self.output_verify(&answer).await?;
+        }
+        if self.echo_flags {
+            self.output_request_flags(client);
+        }
+        if self.emit_timing {
+            self.output_timing(&answer);
+        }
+        if let Some(expected) = self.expect_nsid.as_ref() {
+            Self::check_nsid(&answer, expected)?;
+        }
+        if let Some(expected) = self.expect.as_ref() {
+            if !self.has_expected(&answer, expected) {
+                return Err(format!(
+                    "expected data '{}' not in the answer",
+                    expected
+                )
+                .into());
             }
         }
+        if !self.no_rcode_exit {
+            Self::check_rcode(&answer)?;
+        }
         Ok(())
     }
+
+    /// Returns an error if the response has an error rcode.
+    fn check_rcode(answer: &Answer) -> Result<(), Error> {
+        match answer.message().header().rcode() {
+            Rcode::NOERROR => Ok(()),
+            rcode => Err(Error::Rcode(rcode)),
+        }
+    }
 }
 
 /// # Configuration
 ///
 impl Query {
+    /// Returns the port to use with an explicitly given server.
+    ///
+    /// `server_port` is the port given as part of the server, if any.
+    fn port(&self, server_port: Option<u16>) -> u16 {
+        self.port.or(server_port).unwrap_or(match self.transport() {
+            Transport::Tls | Transport::Quic => 853,
+            Transport::Https => 443,
+            _ => 53,
+        })
+    }
+
     fn timeout(&self) -> Duration {
         Duration::from_secs_f32(self.timeout.unwrap_or(5.))
     }
@@ -213,204 +740,1583 @@ impl Query {
         self.retries.unwrap_or(2)
     }
 
+    /// Returns the backoff between retries over UDP, if any.
+    fn backoff(&self) -> Option<Backoff> {
+        self.retry_backoff.map(|delay| Backoff {
+            delay: Duration::from_millis(delay),
+            factor: self.backoff_factor,
+            jitter: self.jitter,
+        })
+    }
+
+    /// Returns the UDP payload size for explicitly given servers.
+    ///
+    /// An explicitly given size takes precedence. Otherwise, if requested,
+    /// the size configured for the first system server is used and finally
+    /// we fall back to the default of 1232.
     fn udp_payload_size(&self) -> u16 {
-        self.udp_payload_size.unwrap_or(1232)
+        if let Some(size) = self.udp_payload_size {
+            return size;
+        }
+        if self.edns_bufsize_auto {
+            if let Some(server) = ResolvConf::default().servers.first() {
+                return server.udp_payload_size;
+            }
+        }
+        DEFAULT_UDP_PAYLOAD_SIZE
+    }
+
+    fn recording(&self) -> Option<Recording> {
+        if let Some(dir) = self.record.as_ref() {
+            Some(Recording::Record(dir.clone()))
+        } else {
+            self.replay
+                .as_ref()
+                .map(|dir| Recording::Replay(dir.clone()))
+        }
     }
 }
 
-/// # Resolving the server set
+/// The UDP payload size used if nothing else is configured.
+const DEFAULT_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// The smallest UDP payload size servers will honour.
+const MIN_UDP_PAYLOAD_SIZE: u16 = 512;
+
+/// # Sending the request
 ///
 impl Query {
-    /// Resolves a provided server name.
-    async fn host_server(
-        &self,
-        server: &UncertainName<Vec<u8>>,
-    ) -> Result<Client, Error> {
-        let resolver = StubResolver::default();
-        let answer = match server {
-            UncertainName::Absolute(name) => resolver.lookup_host(name).await,
-            UncertainName::Relative(name) => resolver.search_host(name).await,
+    /// Sends the request either via the client or the socket passed in.
+    async fn request(&self, client: &Client) -> Result<Answer, Error> {
+        #[cfg(unix)]
+        if let Some(fd) = self.fd {
+            return self.request_fd(client, fd).await;
         }
-        .map_err(|err| err.to_string())?;
+        client.request(self.create_request()?).await
+    }
 
-        let mut servers = Vec::new();
-        for addr in answer.iter() {
-            if (addr.is_ipv4() && self.ipv6) || (addr.is_ipv6() && self.ipv4)
-            {
-                continue;
-            }
-            servers.push(Server {
-                addr: SocketAddr::new(
-                    addr,
-                    self.port.unwrap_or({
-                        if self.tls {
-                            853
-                        } else {
-                            53
-                        }
-                    }),
-                ),
-                transport: self.transport(),
-                timeout: self.timeout(),
-                retries: self.retries.unwrap_or(2),
-                udp_payload_size: self.udp_payload_size.unwrap_or(1232),
-                tls_hostname: self.tls_hostname.clone(),
-            });
+    /// Sends the request over the TCP socket with the given descriptor.
+    #[cfg(unix)]
+    async fn request_fd(
+        &self,
+        client: &Client,
+        fd: std::os::fd::RawFd,
+    ) -> Result<Answer, Error> {
+        use std::mem::ManuallyDrop;
+        use std::os::fd::FromRawFd;
+
+        if self.is_tls() && self.tls_hostname.is_none() {
+            return Err("--tls-hostname is required for TLS transport".into());
         }
-        Ok(Client::with_servers(servers))
-    }
 
-    /// Resolves a provided server name.
-    fn addr_server(&self, addr: IpAddr) -> Client {
-        Client::with_servers(vec![Server {
-            addr: SocketAddr::new(
-                addr,
-                self.port.unwrap_or(if self.tls { 853 } else { 53 }),
-            ),
-            transport: self.transport(),
+        // SAFETY: We have to trust the user that nobody else uses the
+        //         descriptor. We only take ownership once we know that it
+        //         is a connected TCP socket so that we don’t close, say,
+        //         stderr if it isn’t. Until then, we only ask for socket
+        //         options, which fails if the descriptor isn’t open.
+        let socket = ManuallyDrop::new(unsafe {
+            std::net::TcpStream::from_raw_fd(fd)
+        });
+        let addr = socket
+            .nodelay()
+            .and_then(|_| socket.peer_addr())
+            .map_err(|err| {
+                format!("fd {} is not a connected TCP socket: {}", fd, err)
+            })?;
+        let socket = ManuallyDrop::into_inner(socket);
+        let server = Server {
+            addr,
+            transport: if self.is_tls() {
+                Transport::Tls
+            } else {
+                Transport::Tcp
+            },
             timeout: self.timeout(),
             retries: self.retries(),
             udp_payload_size: self.udp_payload_size(),
             tls_hostname: self.tls_hostname.clone(),
-        }])
+            source: None,
+            proxy: None,
+            backoff: None,
+        };
+        client
+            .request_stream(self.create_request()?, socket, &server)
+            .await
     }
 
-    /// Creates a client based on the system defaults.
-    fn system_server(&self) -> Client {
-        let conf = ResolvConf::default();
-        Client::with_servers(
-            conf.servers
-                .iter()
-                .map(|server| Server {
-                    addr: server.addr,
-                    transport: self.transport(),
-                    timeout: server.request_timeout,
-                    retries: u8::try_from(conf.options.attempts).unwrap_or(2),
-                    udp_payload_size: server.udp_payload_size,
-                    tls_hostname: None,
-                })
-                .collect(),
-        )
+    /// Returns whether a socket was passed in via --fd.
+    #[cfg(unix)]
+    fn has_fd(&self) -> bool {
+        self.fd.is_some()
     }
 
-    fn transport(&self) -> Transport {
-        if self.udp {
-            Transport::Udp
-        } else if self.tls {
-            Transport::Tls
-        } else if self.tcp {
-            Transport::Tcp
-        } else {
-            Transport::UdpTcp
-        }
+    /// Returns whether a socket was passed in via --fd.
+    #[cfg(not(unix))]
+    fn has_fd(&self) -> bool {
+        false
     }
 }
 
-/// # Create the actual query
+/// # Waiting for a change
 ///
 impl Query {
-    /// Creates a new request message.
-    fn create_request(&self) -> RequestMessage<Vec<u8>> {
-        let mut res = MessageBuilder::new_vec();
-
-        res.header_mut().set_ad(self.ad);
-        res.header_mut().set_cd(self.cd);
-        res.header_mut().set_rd(!self.no_rd);
-
-        let mut res = res.question();
-        res.push((&self.qname.to_name(), self.qtype())).unwrap();
-
-        let mut req = RequestMessage::new(res);
-        if self.dnssec_ok {
-            // Avoid touching the EDNS Opt record unless we need to set DO.
-            req.set_dnssec_ok(true);
+    /// Repeats the query until the answer contains the expected data.
+    ///
+    /// A dot is printed to stderr for every answer that doesn’t match yet.
+    /// Failed queries are treated as not matching. Gives up after the
+    /// configured timeout.
+    async fn wait_for_expected(
+        &self,
+        client: &Client,
+    ) -> Result<Answer, Error> {
+        let expected = self.expect.as_deref().unwrap_or_default();
+        let start = Instant::now();
+        let timeout = Duration::from_secs(self.wait_timeout);
+        let interval = Duration::from_secs(self.interval);
+        loop {
+            if let Ok(answer) = self.request(client).await {
+                if self.has_expected(&answer, expected) {
+                    eprintln!(
+                        "\n;; Expected data found after {} seconds.",
+                        start.elapsed().as_secs()
+                    );
+                    return Ok(answer);
+                }
+            }
+            eprint!(".");
+            if start.elapsed() + interval > timeout {
+                eprintln!();
+                return Err(format!(
+                    "expected data '{}' not found after {} seconds",
+                    expected, self.wait_timeout
+                )
+                .into());
+            }
+            tokio::time::sleep(interval).await;
         }
-        req
     }
-}
 
-/// # Get an authoritative answer
-impl Query {
-    async fn auth_answer(&self) -> Result<Answer, Error> {
-        let servers = {
-            let resolver = StubResolver::new();
-            let apex = self.get_apex(&resolver).await?;
-            let ns_set = self.get_ns_set(&apex, &resolver).await?;
-            self.get_ns_addrs(&ns_set, &resolver).await?
+    /// Returns whether the answer has a record with the expected data.
+    ///
+    /// The record needs to be of the query type. Since the data is compared
+    /// in presentation format, case and trailing dots are ignored.
+    fn has_expected(&self, answer: &Answer, expected: &str) -> bool {
+        let qtype = self.qtype();
+        let expected = expected.trim().trim_end_matches('.');
+        let Ok(section) = answer.message().answer() else {
+            return false;
         };
-        Client::with_servers(servers)
-            .query((self.qname.to_name(), self.qtype()))
-            .await
+        section
+            .into_records::<AllRecordData<_, _>>()
+            .filter_map(Result::ok)
+            .filter(|record| record.rtype() == qtype)
+            .any(|record| {
+                record
+                    .data()
+                    .to_string()
+                    .trim_end_matches('.')
+                    .eq_ignore_ascii_case(expected)
+            })
     }
+}
 
-    /// Tries to determine the apex of the zone the requested records live in.
-    async fn get_apex(
+/// # Benchmarking
+///
+impl Query {
+    /// Sends the request repeatedly and prints latency statistics.
+    ///
+    /// The requests are spread evenly over `--concurrency` tasks, each of
+    /// which sends its share one after the other.
+    async fn benchmark(
         &self,
-        resolv: &StubResolver,
-    ) -> Result<Name<Vec<u8>>, Error> {
-        // Ask for the SOA record for the qname.
-        let qname = self.qname.to_name();
-        let response = resolv.query((&qname, Rtype::SOA)).await?;
+        client: &Client,
+        count: u32,
+    ) -> Result<(), Error> {
+        if self.has_fd() {
+            return Err(Error::config(
+                "--repeat cannot be used with a socket passed in via --fd",
+            ));
+        }
+        let tasks = self.concurrency.min(count);
+        let tasks = (0..tasks)
+            .map(|i| {
+                let share = count / tasks + u32::from(i < count % tasks);
+                let query = self.clone();
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let mut results = Vec::new();
+                    for _ in 0..share {
+                        results.push(query.request(&client).await.map(
+                            |answer| {
+                                (
+                                    answer.stats().duration,
+                                    answer.message().header().rcode(),
+                                )
+                            },
+                        ));
+                    }
+                    results
+                })
+            })
+            .collect::<Vec<_>>();
 
-        // The SOA record is in the answer section if the qname is the apex
-        // or in the authority section with the apex as the owner name
-        // otherwise.
-        let mut answer = response.answer()?.limit_to_in::<Soa<_>>();
-        if let Some(soa) = answer.next() {
-            let soa = soa?;
-            if *soa.owner() == qname {
-                return Ok(qname.clone());
+        let mut stats = LatencyStats::default();
+        for task in tasks {
+            let results = task.await.map_err(|err| err.to_string())?;
+            for res in results {
+                stats.add(res);
             }
-            // Strange SOA in the answer section, let’s continue with
-            // the authority section.
         }
-
-        let mut authority =
-            answer.next_section()?.unwrap().limit_to_in::<Soa<_>>();
-        if let Some(soa) = authority.next() {
-            let soa = soa?;
-            return Ok(soa.owner().to_name());
+        stats.print();
+        if stats.durations.is_empty() {
+            return Err(Error::transport("all queries failed"));
         }
-
-        Err("no SOA record".into())
+        Ok(())
     }
+}
 
-    /// Tries to find the NS set for the given apex name.
-    async fn get_ns_set(
-        &self,
-        apex: &Name<Vec<u8>>,
-        resolv: &StubResolver,
-    ) -> Result<Vec<Name<Vec<u8>>>, Error> {
-        let response = resolv.query((apex, Rtype::NS)).await?;
-        let mut res = Vec::new();
-        for record in response.answer()?.limit_to_in::<Ns<_>>() {
-            let record = record?;
-            if *record.owner() != apex {
+/// # Interactive mode
+///
+impl Query {
+    /// Reads queries from stdin and sends each of them.
+    ///
+    /// Each line contains a query name and optionally query types. All
+    /// queries use the same client, so TCP and TLS connections to the
+    /// servers stay open between them. Errors are reported and the next
+    /// line is read. The session ends at the end of input.
+    async fn interactive(&self, client: &Client) -> Result<(), Error> {
+        if self.has_fd() {
+            return Err(Error::config(
+                "--interactive cannot be used with a socket passed in via \
+                 --fd",
+            ));
+        }
+        let stdin = io::stdin();
+        let prompt = stdin.is_terminal();
+        let mut line = String::new();
+        let mut first = true;
+        loop {
+            if prompt {
+                print!("> ");
+                io::stdout().flush().map_err(Error::output)?;
+            }
+            line.clear();
+
+            // Reading blocks, but only the thread driving this session
+            // and not the runtime’s workers handling the connections.
+            if stdin.lock().read_line(&mut line)? == 0 {
+                if prompt {
+                    println!();
+                }
+                return Ok(());
+            }
+            if line.trim().is_empty() {
                 continue;
             }
-            res.push(record.data().nsdname().to_name());
+            if !first && !prompt {
+                println!();
+            }
+            first = false;
+            let res = match self.interactive_query(&line) {
+                Ok(query) => query.execute_qtypes(client).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = res {
+                eprintln!("{}", err);
+            }
         }
+    }
 
-        // We could technically get the A and AAAA records from the additional
-        // section, but we’re going to ask anyway, so: meh.
-
-        Ok(res)
+    /// Creates the query for a line read in interactive mode.
+    fn interactive_query(&self, line: &str) -> Result<Self, Error> {
+        let mut words = line.split_whitespace();
+        let mut query = self.clone();
+        query.qname = Some(NameOrAddr::from_str(
+            words.next().ok_or("missing query name")?,
+        )?);
+        query.qtype =
+            words.map(parse_rtype).collect::<Result<Vec<_>, _>>()?;
+        if query.strict_name {
+            query.qname().check_hostname()?;
+        }
+        if !query.force
+            && query
+                .qtypes()
+                .any(|qtype| qtype == Rtype::AXFR || qtype == Rtype::IXFR)
+        {
+            return Err(
+                "AXFR and IXFR are not supported by the query command.\n\
+                 (Use --force to query anyway.)"
+                    .into(),
+            );
+        }
+        Ok(query)
     }
+}
 
-    /// Tries to get all the addresses for all the name servers.
-    async fn get_ns_addrs(
-        &self,
-        ns_set: &[Name<Vec<u8>>],
-        resolv: &StubResolver,
-    ) -> Result<Vec<Server>, Error> {
-        let mut res = HashSet::new();
-        for ns in ns_set {
-            for addr in resolv.lookup_host(ns).await?.iter() {
-                res.insert(addr);
+/// # Resolving the server set
+///
+impl Query {
+    /// Creates the client for the requested server set.
+    ///
+    /// The servers are tried in the order given. A host name expands into
+    /// all its addresses.
+    async fn client(&self) -> Result<Client, Error> {
+        let server_specs = self.server_specs()?;
+        if server_specs.is_empty() {
+            if self.port.is_some() {
+                return Err(Error::config("--port requires a server"));
             }
+            if self.uses_tls() {
+                return Err("--server is required for TLS transport".into());
+            }
+            return self.system_server();
         }
-        Ok(res
-            .into_iter()
+        let mut servers = Vec::new();
+        for server in &server_specs {
+            if server.port.is_some() && self.port.is_some() {
+                return Err(
+                    "the port must not be given in both --server and --port"
+                        .into(),
+                );
+            }
+            match &server.name {
+                ServerName::Name(host) => {
+                    servers.extend(self.host_server(host, server.port).await?)
+                }
+                ServerName::Addr(addr) => {
+                    if self.uses_tls() && self.tls_hostname.is_none() {
+                        return Err(
+                            "--tls-hostname is required for TLS transport"
+                                .into(),
+                        );
+                    }
+                    servers.push(self.addr_server(*addr, server.port))
+                }
+            }
+        }
+        Ok(Client::with_servers(servers))
+    }
+
+    /// Returns the servers to send the query to.
+    ///
+    /// A dig-style @server may only be combined with --server if both
+    /// refer to the same server. It always overrides DNSI_SERVER which is
+    /// only a default. If no server is given on the command
+    /// line, the one from the DNSI_SERVER environment variable is used
+    /// unless the query doesn’t use a server at all, as with --trace or
+    /// --fd.
+    fn server_specs(&self) -> Result<Vec<ServerSpec>, Error> {
+        if let Some(at_server) = self.at_server.as_ref() {
+            if self.server.iter().any(|server| !server.is_same(at_server)) {
+                return Err(Error::config(
+                    "the server given via @ differs from the one given via \
+                     --server",
+                ));
+            }
+            return Ok(vec![at_server.clone()]);
+        }
+        if !self.server.is_empty() || self.trace || self.has_fd() {
+            return Ok(self.server.clone());
+        }
+        Self::env_server()
+    }
+
+    /// Returns the default server from the DNSI_SERVER variable, if set.
+    fn env_server() -> Result<Vec<ServerSpec>, Error> {
+        match std::env::var("DNSI_SERVER") {
+            Ok(server) if !server.is_empty() => ServerSpec::from_str(&server)
+                .map(|server| vec![server])
+                .map_err(|err| {
+                    Error::config(format!("invalid DNSI_SERVER: {}", err))
+                }),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Resolves a provided server name.
+    ///
+    /// Unless given explicitly, the TLS host name is the server name.
+    async fn host_server(
+        &self,
+        server: &UncertainName<Vec<u8>>,
+        port: Option<u16>,
+    ) -> Result<Vec<Server>, Error> {
+        let resolver = StubResolver::default();
+        let answer = match server {
+            UncertainName::Absolute(name) => resolver.lookup_host(name).await,
+            UncertainName::Relative(name) => resolver.search_host(name).await,
+        }
+        .map_err(|err| err.to_string())?;
+
+        let udp_payload_size = self.udp_payload_size();
+        let tls_hostname = self
+            .tls_hostname
+            .clone()
+            .unwrap_or_else(|| server.to_string());
+        let mut servers = Vec::new();
+        for addr in answer.iter() {
+            if (addr.is_ipv4() && self.ipv6) || (addr.is_ipv6() && self.ipv4)
+            {
+                continue;
+            }
+            servers.push(Server {
+                addr: SocketAddr::new(addr, self.port(port)),
+                transport: self.transport(),
+                timeout: self.timeout(),
+                retries: self.retries(),
+                udp_payload_size,
+                tls_hostname: Some(tls_hostname.clone()),
+                source: self.source,
+                proxy: self.proxy.clone(),
+                backoff: self.backoff(),
+            });
+        }
+        Ok(servers)
+    }
+
+    /// Creates the server for a provided address.
+    fn addr_server(&self, addr: IpAddr, port: Option<u16>) -> Server {
+        Server {
+            addr: SocketAddr::new(addr, self.port(port)),
+            transport: self.transport(),
+            timeout: self.timeout(),
+            retries: self.retries(),
+            udp_payload_size: self.udp_payload_size(),
+            tls_hostname: self.tls_hostname.clone(),
+            source: self.source,
+            proxy: self.proxy.clone(),
+            backoff: self.backoff(),
+        }
+    }
+
+    /// Creates a client based on the system defaults.
+    ///
+    /// Only servers of the address family selected via `--ipv4` or
+    /// `--ipv6` are used.
+    fn system_server(&self) -> Result<Client, Error> {
+        let conf = ResolvConf::default();
+        let servers = conf
+            .servers
+            .iter()
+            .filter(|server| {
+                let addr = server.addr;
+                !((addr.is_ipv4() && self.ipv6)
+                    || (addr.is_ipv6() && self.ipv4))
+            })
+            .map(|server| Server {
+                addr: server.addr,
+                transport: self.transport(),
+                timeout: server.request_timeout,
+                retries: u8::try_from(conf.options.attempts).unwrap_or(2),
+                udp_payload_size: self
+                    .udp_payload_size
+                    .unwrap_or(server.udp_payload_size),
+                tls_hostname: None,
+                source: self.source,
+                proxy: self.proxy.clone(),
+                backoff: self.backoff(),
+            })
+            .collect::<Vec<_>>();
+        if servers.is_empty() {
+            return Err(Error::config(if self.ipv4 {
+                "no IPv4 servers in the system configuration"
+            } else if self.ipv6 {
+                "no IPv6 servers in the system configuration"
+            } else {
+                "no servers in the system configuration"
+            }));
+        }
+        Ok(Client::with_servers(servers))
+    }
+
+    /// Looks up the host name of the server an answer came from.
+    ///
+    /// Returns `None` if there is no PTR record for the address or the
+    /// lookup fails.
+    async fn lookup_server_name(answer: &Answer) -> Option<String> {
+        let addr = answer.stats().server_addr.ip();
+        let names = StubResolver::new().lookup_addr(addr).await.ok()?;
+        let name = names.iter().next()?;
+        Some(name.to_string())
+    }
+
+    /// Prints the request in the selected output format.
+    ///
+    /// The message ID may still be changed by the transport.
+    fn output_request(&self) -> Result<(), Error> {
+        let msg = Message::from_octets(self.create_request()?.to_vec()?)
+            .map_err(|_| "cannot print an invalid request")?;
+        println!(";; Request:");
+        self.output.print_message(msg.for_slice_ref())?;
+        println!();
+        Ok(())
+    }
+
+    /// Prints the servers the client is going to use.
+    fn output_servers(client: &Client) {
+        if client.servers().is_empty() {
+            println!(";; No servers.");
+        }
+        for server in client.servers() {
+            match server.proxy.as_ref() {
+                Some(proxy) => println!(
+                    ";; Server: {} via {} through {}",
+                    server.addr, server.transport, proxy
+                ),
+                None => println!(
+                    ";; Server: {} via {}",
+                    server.addr, server.transport
+                ),
+            }
+        }
+        println!();
+    }
+
+    /// Returns the transport to use.
+    ///
+    /// The command line flags take precedence over --transport which
+    /// may also have been given via an environment variable.
+    fn transport(&self) -> Transport {
+        if self.udp {
+            Transport::Udp
+        } else if self.tls {
+            Transport::Tls
+        } else if self.tcp {
+            Transport::Tcp
+        } else if self.https {
+            Transport::Https
+        } else if self.quic {
+            Transport::Quic
+        } else if self.opportunistic_tls {
+            Transport::OpportunisticTls
+        } else {
+            match self.transport {
+                Some(TransportArg::Udp) => Transport::Udp,
+                Some(TransportArg::Tcp) => Transport::Tcp,
+                Some(TransportArg::Tls) => Transport::Tls,
+                Some(TransportArg::Https) => Transport::Https,
+                Some(TransportArg::Quic) => Transport::Quic,
+                Some(TransportArg::UdpTcp) | None => Transport::UdpTcp,
+            }
+        }
+    }
+
+    fn is_tls(&self) -> bool {
+        matches!(self.transport(), Transport::Tls)
+    }
+
+    /// Returns whether the transport uses TLS, i.e., isn’t UDP or TCP.
+    fn uses_tls(&self) -> bool {
+        matches!(
+            self.transport(),
+            Transport::Tls | Transport::Https | Transport::Quic
+        )
+    }
+}
+
+/// # Create the actual query
+///
+impl Query {
+    /// Creates a new request message.
+    fn create_request(&self) -> Result<RequestMessage<Vec<u8>>, Error> {
+        self.create_request_for(&self.request_qname())
+    }
+
+    /// Returns the query name as it is sent in the request.
+    ///
+    /// With --0x20, the case of its letters is randomized.
+    fn request_qname(&self) -> Name<Vec<u8>> {
+        let qname = self.qname().to_name();
+        if self.case_randomization {
+            randomize_case(&qname, self.case_seed)
+        } else {
+            qname
+        }
+    }
+
+    /// Creates the request for the given query name.
+    ///
+    /// When tracing, the RD flag is never set.
+    ///
+    /// Fails if the EDNS options don’t fit into the OPT record.
+    fn create_request_for(
+        &self,
+        qname: &Name<Vec<u8>>,
+    ) -> Result<RequestMessage<Vec<u8>>, Error> {
+        let mut res = MessageBuilder::new_vec();
+
+        res.header_mut().set_ad(self.ad);
+        res.header_mut().set_cd(self.cd);
+        res.header_mut().set_rd(!self.no_rd && !self.trace);
+        res.header_mut().set_opcode(self.opcode);
+        match self.id {
+            Some(id) => res.header_mut().set_id(id),
+            None => res.header_mut().set_random_id(),
+        }
+        if let Some(z_bits) = self.z_bits {
+            res.header_mut().set_z(z_bits != 0);
+        }
+
+        let mut res = res.question();
+        res.push((qname, self.qtype(), self.class)).unwrap();
+
+        let mut req = RequestMessage::new(res);
+        if self.dnssec_ok {
+            // Avoid touching the EDNS Opt record unless we need to set DO.
+            req.set_dnssec_ok(true);
+        }
+        if self.nsid || self.expect_nsid.is_some() {
+            Self::add_raw_opt(&mut req, OptionCode::NSID, b"".as_slice())?;
+        }
+        if let Some(cookie) = self.cookie_raw.as_ref() {
+            // This deliberately skips any checks on the cookie length.
+            Self::add_raw_opt(&mut req, OptionCode::COOKIE, &cookie.0)?;
+        }
+        if !self.keytag.is_empty() {
+            // RFC 8145: a list of key tags in network byte order.
+            let data = self
+                .keytag
+                .iter()
+                .flat_map(|tag| tag.to_be_bytes())
+                .collect::<Vec<_>>();
+            Self::add_raw_opt(&mut req, OptionCode::KEY_TAG, &data)?;
+        }
+        if let Some(chain) = self.chain.as_ref() {
+            // RFC 7901: the closest trust point in uncompressed wire format.
+            Self::add_raw_opt(&mut req, OptionCode::CHAIN, chain.as_slice())?;
+        }
+        if self.expire {
+            // RFC 7314: the option is empty in queries.
+            Self::add_raw_opt(&mut req, OptionCode::EXPIRE, b"".as_slice())?;
+        }
+        for (code, algs) in [
+            (OptionCode::DAU, &self.dau),
+            (OptionCode::DHU, &self.dhu),
+            (OptionCode::N3U, &self.n3u),
+        ] {
+            if !algs.is_empty() {
+                // RFC 6975: one octet per algorithm number.
+                Self::add_raw_opt(&mut req, code, algs.as_slice())?;
+            }
+        }
+        if let Some(subnet) = self.subnet.as_ref() {
+            req.add_opt(&ClientSubnet::new(
+                subnet.source_prefix,
+                subnet.scope_prefix,
+                subnet.addr,
+            ))
+            .map_err(|_| Self::long_opt(OptionCode::CLIENT_SUBNET))?;
+        }
+        for opt in &self.ednsopt {
+            Self::add_raw_opt(&mut req, opt.code, opt.data.0.as_slice())?;
+        }
+        match self.padding {
+            Some(Some(len)) => {
+                // The option header adds another four octets.
+                let len = usize::from(len);
+                if self.request_len(&mut req) + 4 + len
+                    > usize::from(u16::MAX)
+                {
+                    return Err(Error::config(
+                        "the padding doesn’t fit into the request",
+                    ));
+                }
+                Self::add_raw_opt(
+                    &mut req,
+                    OptionCode::PADDING,
+                    &vec![0u8; len],
+                )?;
+            }
+            Some(None) => {
+                self.pad_request(&mut req, REQUEST_PADDING_BLOCK)?
+            }
+            None if self.edns_padding_response_check => {
+                self.pad_request(&mut req, REQUEST_PADDING_BLOCK)?
+            }
+            None => {}
+        }
+        Ok(req)
+    }
+
+    /// Adds an EDNS option with the given raw data to the request.
+    fn add_raw_opt(
+        req: &mut RequestMessage<Vec<u8>>,
+        code: OptionCode,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let opt = UnknownOptData::new(code, data)
+            .map_err(|_| Self::long_opt(code))?;
+        req.add_opt(&opt).map_err(|_| Self::long_opt(code))
+    }
+
+    /// Returns the error for an option that doesn’t fit the OPT record.
+    fn long_opt(code: OptionCode) -> Error {
+        Error::config(format!(
+            "EDNS option {} doesn’t fit into the OPT record",
+            code
+        ))
+    }
+
+    /// Adds a padding option so the request is a multiple of `block` long.
+    fn pad_request(
+        &self,
+        req: &mut RequestMessage<Vec<u8>>,
+        block: usize,
+    ) -> Result<(), Error> {
+        // The option header adds another four octets.
+        let len = self.request_len(req) + 4;
+        let padding = (block - len % block) % block;
+        Self::add_raw_opt(req, OptionCode::PADDING, &vec![0u8; padding])
+    }
+
+    /// Returns the length of the request including its OPT record.
+    fn request_len(&self, req: &mut RequestMessage<Vec<u8>>) -> usize {
+        // Make sure the OPT record exists so it is included in the length.
+        req.set_udp_payload_size(self.udp_payload_size());
+        req.to_vec().map(|msg| msg.len()).unwrap_or_default()
+    }
+}
+
+/// The block size to pad requests to as recommended by RFC 8467.
+const REQUEST_PADDING_BLOCK: usize = 128;
+
+/// The block size servers should pad responses to according to RFC 8467.
+const RESPONSE_PADDING_BLOCK: usize = 468;
+
+/// # Interpreting the answer
+///
+impl Query {
+    /// Prints which transport was used in opportunistic TLS mode.
+    fn output_opportunistic_tls_note(answer: &Answer) {
+        match answer.stats().server_proto {
+            Protocol::Tls => println!("\n;; Opportunistic TLS: used TLS."),
+            proto => println!(
+                "\n;; Opportunistic TLS: TLS not available, used {}.",
+                proto
+            ),
+        }
+    }
+
+    /// Returns whether the answer is printed for humans rather than scripts.
+    ///
+    /// Notes on the answer are only added to such output so they don’t end
+    /// up in what a script captures.
+    fn human_output(&self) -> bool {
+        !self.first
+            && !self.as_update_prereq
+            && !self.output.answer_only
+            && matches!(
+                self.output.format,
+                OutputFormat::Dig | OutputFormat::Friendly
+            )
+    }
+
+    /// Prints a note explaining the meaning of the AD flag if it is set.
+    fn output_ad_note(&self, answer: &Answer) {
+        if !answer.message().header().ad() {
+            return;
+        }
+        if self.trust_ad {
+            println!("\n;; AD set: resolver asserts DNSSEC-validated.");
+        } else {
+            println!(
+                "\n;; AD set: resolver asserts DNSSEC-validated, but the \
+                 channel to it is\n\
+                 ;; not known to be trusted. (Use --trust-ad if it is.)"
+            );
+        }
+        if !self.dnssec_ok {
+            println!(
+                ";; DNSSEC records were not requested. \
+                 (Use --do to include them.)"
+            );
+        }
+    }
+
+    /// Prints whether the response preserved the case of the query name.
+    ///
+    /// Servers should copy the question into the response as is. Some
+    /// forwarders don’t, which breaks the use of 0x20 encoding as a
+    /// defence against spoofing.
+    fn output_case_check(&self, answer: &Answer) {
+        let sent = self.request_qname();
+        let received = match answer.message().sole_question() {
+            Ok(question) => question.qname().to_name::<Vec<u8>>(),
+            Err(_) => {
+                println!(
+                    "\n;; 0x20: the response has no question to compare."
+                );
+                return;
+            }
+        };
+        if received.as_slice() == sent.as_slice() {
+            println!("\n;; 0x20: the case of the query name was preserved.");
+        } else {
+            println!(
+                "\n;; WARNING: 0x20 mismatch: sent {} but received {}.",
+                sent, received
+            );
+        }
+    }
+
+    /// Prints a hint if a non-recursive query went to a recursive server.
+    fn output_recursion_hint(&self, answer: &Answer) {
+        let msg = answer.message();
+        if self.no_rd
+            && msg.header().ra()
+            && msg.header_counts().ancount() == 0
+        {
+            println!(
+                "\n;; No answer to a query without RD from a server \
+                 offering recursion.\n\
+                 ;; The server may only be a recursive resolver or the \
+                 name may not exist."
+            );
+        }
+    }
+}
+
+/// # Analysing the answer
+///
+impl Query {
+    /// Checks that the response contains the expected NSID.
+    fn check_nsid(answer: &Answer, expected: &HexData) -> Result<(), Error> {
+        let msg = answer.msg_slice();
+        let nsid = msg.opt().and_then(|opt| {
+            opt.opt()
+                .iter::<UnknownOptData<_>>()
+                .filter_map(Result::ok)
+                .find(|option| option.code() == OptionCode::NSID)
+                .map(|option| HexData(option.data().to_vec()))
+        });
+        match nsid {
+            Some(nsid) if nsid.0 == expected.0 => Ok(()),
+            Some(nsid) => Err(format!(
+                "NSID mismatch: expected {}, received {}",
+                expected, nsid
+            )
+            .into()),
+            None => Err(format!(
+                "NSID mismatch: expected {}, received none",
+                expected
+            )
+            .into()),
+        }
+    }
+
+    /// Returns a description of how the request was padded.
+    fn request_padding(&self) -> String {
+        match self.padding {
+            Some(Some(len)) => {
+                format!("request padded with {} octets", len)
+            }
+            _ => format!(
+                "request padded to a multiple of {} octets",
+                REQUEST_PADDING_BLOCK
+            ),
+        }
+    }
+
+    /// Prints whether and how the response was padded.
+    fn output_padding_check(&self, answer: &Answer) {
+        let msg = answer.msg_slice();
+        let size = msg.as_slice().len();
+        let padding = msg.opt().and_then(|opt| {
+            opt.opt().iter::<AllOptData<_, _>>().find_map(|option| {
+                match option {
+                    Ok(AllOptData::Padding(padding)) => {
+                        Some(padding.as_slice().len())
+                    }
+                    _ => None,
+                }
+            })
+        });
+
+        println!("\n;; PADDING CHECK: {}.", self.request_padding());
+        match padding {
+            None => {
+                println!(";; PADDING CHECK: response is not padded.");
+            }
+            Some(len) if size % RESPONSE_PADDING_BLOCK == 0 => {
+                println!(
+                    ";; PADDING CHECK: response padded with {} octets to \
+                     {} octets, a multiple of {} as recommended.",
+                    len, size, RESPONSE_PADDING_BLOCK
+                );
+            }
+            Some(len) => {
+                let block = [256, 128, 64, 32, 16]
+                    .into_iter()
+                    .find(|block| size % block == 0);
+                match block {
+                    Some(block) => println!(
+                        ";; PADDING CHECK: response padded with {} octets \
+                         to {} octets, a multiple of {} rather than the \
+                         recommended {}.",
+                        len, size, block, RESPONSE_PADDING_BLOCK
+                    ),
+                    None => println!(
+                        ";; PADDING CHECK: response padded with {} octets \
+                         to {} octets, which is not a multiple of any \
+                         common block size.",
+                        len, size
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Prints a summary of the exchange as a single line of key-value pairs.
+    fn output_timing(&self, answer: &Answer) {
+        let stats = answer.stats();
+        println!(
+            "qname={} qtype={} server={} proto={} rcode={} rtt_ms={} size={}",
+            self.qname().to_name(),
+            self.qtype(),
+            stats.server_addr,
+            stats.server_proto,
+            answer.message().header().rcode(),
+            stats.duration.num_milliseconds(),
+            answer.message().as_slice().len(),
+        );
+    }
+
+    /// Prints the scope prefix length of the returned client subnet.
+    ///
+    /// The scope tells how specific to the client subnet the answer is.
+    /// A scope of zero means the answer is valid for all clients.
+    fn output_subnet_scope(answer: &Answer) {
+        let msg = answer.msg_slice();
+        let subnet = msg.opt().and_then(|opt| {
+            opt.opt().iter::<ClientSubnet>().find_map(Result::ok)
+        });
+        match subnet {
+            Some(subnet) => println!(
+                "\n;; ECS scope: /{} (source /{}, address {})",
+                subnet.scope_prefix_len(),
+                subnet.source_prefix_len(),
+                subnet.addr()
+            ),
+            None => {
+                println!("\n;; ECS scope: no client subnet in response")
+            }
+        }
+    }
+
+    /// Prints the record data of the first answer record of the query type.
+    ///
+    /// Fails if there is no such record so scripts can tell.
+    fn output_first(&self, answer: &Answer) -> Result<(), Error> {
+        let qtype = self.qtype();
+        let first = answer
+            .message()
+            .answer()?
+            .into_records::<AllRecordData<_, _>>()
+            .filter_map(Result::ok)
+            .find(|record| qtype == Rtype::ANY || record.rtype() == qtype);
+        match first {
+            Some(record) => {
+                println!("{}", record.data());
+                Ok(())
+            }
+            None => Err(format!("no {} record in the answer", qtype).into()),
+        }
+    }
+
+    /// Prints the records of the answer section as nsupdate prerequisites.
+    ///
+    /// Every record becomes a value-dependent `yxrrset` prerequisite.
+    /// Since all lines of other output start with a semicolon, they are
+    /// comments for nsupdate and the output can be fed to it as is.
+    fn output_update_prereqs(answer: &Answer) -> Result<(), Error> {
+        for record in answer
+            .message()
+            .answer()?
+            .into_records::<AllRecordData<_, _>>()
+        {
+            let record = record?;
+            let mut owner = record.owner().to_string();
+            if !owner.ends_with('.') {
+                owner.push('.');
+            }
+            println!(
+                "prereq yxrrset {} {} {} {}",
+                owner,
+                record.class(),
+                record.rtype(),
+                record.data()
+            );
+        }
+        Ok(())
+    }
+
+    /// Prints the numeric codes of all options in the OPT record.
+    fn output_opt_codes(answer: &Answer) {
+        let msg = answer.msg_slice();
+        match msg.opt() {
+            Some(opt) => {
+                let codes = opt
+                    .opt()
+                    .iter::<UnknownOptData<_>>()
+                    .filter_map(Result::ok)
+                    .map(|option| option.code().to_int().to_string())
+                    .collect::<Vec<_>>();
+                println!("\n;; OPT codes: {}", codes.join(","));
+            }
+            None => println!("\n;; OPT codes: no OPT record in response"),
+        }
+    }
+
+    /// Prints the effective parameters of the request.
+    ///
+    /// This makes the output self-documenting when it is passed on.
+    fn output_request_flags(&self, client: &Client) {
+        let mut flags = Vec::new();
+        if !self.no_rd {
+            flags.push("rd");
+        }
+        if self.ad {
+            flags.push("ad");
+        }
+        if self.cd {
+            flags.push("cd");
+        }
+        if self.dnssec_ok {
+            flags.push("do");
+        }
+        let udp_payload_size = client
+            .servers()
+            .first()
+            .map_or(self.udp_payload_size(), |server| {
+                server.udp_payload_size
+            });
+        let options = self
+            .create_request()
+            .ok()
+            .and_then(|req| req.to_vec().ok())
+            .and_then(|msg| Message::from_octets(msg).ok())
+            .and_then(|msg| {
+                msg.opt().map(|opt| {
+                    opt.opt()
+                        .iter::<UnknownOptData<_>>()
+                        .filter_map(Result::ok)
+                        .map(|option| option.code().to_string())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .unwrap_or_default();
+
+        println!(
+            "\n;; REQUEST: flags: {}; udp: {}; transport: {}; options: {}",
+            if flags.is_empty() {
+                "none".into()
+            } else {
+                flags.join(" ")
+            },
+            udp_payload_size,
+            self.transport(),
+            if options.is_empty() {
+                "none".into()
+            } else {
+                options.join(", ")
+            },
+        );
+    }
+
+    /// Prints statistics on the name compression used in the answer.
+    fn output_compression_stats(&self, answer: &Answer) {
+        match CompressionStats::from_message(answer.message().as_slice()) {
+            Some(stats) => println!("\n;; COMPRESSION: {}", stats),
+            None => println!(
+                "\n;; COMPRESSION: malformed message, cannot analyse"
+            ),
+        }
+    }
+}
+
+/// # Comparing transports
+///
+impl Query {
+    /// Compares the answers received over the different transports.
+    ///
+    /// The query is sent to the first server of the client over UDP and
+    /// TCP. If a TLS host name is known, it is also sent over TLS. The
+    /// answer sections received via TCP and TLS are then compared to the
+    /// one received via UDP. A transport that fails is reported as such.
+    async fn output_transport_comparison(
+        &self,
+        client: &Client,
+    ) -> Result<(), Error> {
+        let Some(server) = client.servers().first() else {
+            return Err("no server to compare transports with".into());
+        };
+
+        // The server’s port is only kept for the transport it was given
+        // for. The other transports use their default port.
+        let mut plain = server.clone();
+        if self.uses_tls() {
+            plain.addr.set_port(53);
+        }
+        let request = self.create_request()?;
+        let udp = client.request_udp(request.clone(), &plain).await;
+        let mut answers =
+            vec![("TCP", client.request_tcp(request.clone(), &plain).await)];
+        if let Some(tls_hostname) = server.tls_hostname.as_ref() {
+            let mut tls = server.clone();
+            tls.addr
+                .set_port(self.comparison_tls_port(server.addr.port()));
+            tls.tls_hostname = Some(tls_hostname.clone());
+            answers.push(("TLS", client.request_tls(request, &tls).await));
+        }
+
+        println!("\n;; Comparing transports with {}:", plain.addr.ip());
+        let mut all_ok = true;
+        let udp = match udp {
+            Ok(udp) => {
+                if udp.message().header().tc() {
+                    println!(";; UDP response truncated (TC flag set).");
+                }
+                Some(udp)
+            }
+            Err(err) => {
+                println!(";; UDP: failed: {}", err);
+                all_ok = false;
+                None
+            }
+        };
+        let mut all_match = true;
+        for (transport, answer) in answers {
+            let answer = match answer {
+                Ok(answer) => answer,
+                Err(err) => {
+                    println!(";; {}: failed: {}", transport, err);
+                    all_ok = false;
+                    continue;
+                }
+            };
+            let Some(udp) = udp.as_ref() else {
+                println!(";; {} ANSWER not compared without UDP.", transport);
+                continue;
+            };
+            let diff = Self::diff_answers(udp.message(), answer.message())?;
+            let summary = DiffSummary::new(&diff);
+            if summary.is_match() {
+                println!(";; {} ANSWER matches UDP.", transport);
+            } else {
+                println!(";; {} ANSWER differs from UDP:", transport);
+                self.output_diff(diff);
+                all_match = false;
+            }
+            println!(";; {}: {}", transport.to_lowercase(), summary);
+        }
+        if !all_ok {
+            return Err("not all transports have succeeded".into());
+        }
+        if !all_match {
+            return Err("ANSWER differs between transports".into());
+        }
+        Ok(())
+    }
+
+    /// Returns the port to use for TLS when comparing transports.
+    ///
+    /// The given port of the server is only kept if TLS was asked for
+    /// with an explicit port. Otherwise, notably for HTTPS and QUIC, the
+    /// default port 853 is used.
+    fn comparison_tls_port(&self, port: u16) -> u16 {
+        let explicit = self.port.is_some()
+            || self.server_specs().is_ok_and(|specs| {
+                specs.iter().any(|server| server.port.is_some())
+            });
+        if self.is_tls() && explicit {
+            port
+        } else {
+            853
+        }
+    }
+}
+
+/// # Following referrals
+///
+impl Query {
+    /// Follows referrals starting with the given answer.
+    ///
+    /// Every response received along the way is printed. Stops once a
+    /// response isn’t a referral or after `MAX_REFERRALS` steps and
+    /// returns the last response.
+    async fn follow_referrals(
+        &self,
+        qname: &Name<Vec<u8>>,
+        answer: &Answer,
+    ) -> Result<Answer, Error> {
+        let resolver = StubResolver::new();
+        let mut answer = answer.clone();
+        let mut parent = None;
+        for _ in 0..MAX_REFERRALS {
+            let Some((zone, ns_set)) =
+                Self::get_referral(qname, answer.message())?
+            else {
+                return Ok(answer);
+            };
+
+            // Referrals need to lead us downwards or we may loop forever.
+            if let Some(parent) = parent.as_ref() {
+                if zone == *parent || !zone.ends_with(parent) {
+                    return Err(format!(
+                        "referral from {} to {} does not lead downwards",
+                        parent, zone
+                    )
+                    .into());
+                }
+            }
+
+            let servers = self
+                .referral_servers(answer.message(), &ns_set, &resolver)
+                .await?;
+            if servers.is_empty() {
+                return Err(format!(
+                    "no addresses for the name servers of {}",
+                    zone
+                )
+                .into());
+            }
+            println!(
+                "\n;; Following referral to {} ({} servers)\n",
+                zone,
+                servers.len()
+            );
+            answer = Client::with_servers(servers)
+                .request(self.create_request_for(qname)?)
+                .await?;
+            self.output.print(&answer)?;
+            parent = Some(zone);
+        }
+        Err(format!("more than {} referrals", MAX_REFERRALS).into())
+    }
+
+    /// Returns the delegated zone and its NS set if `msg` is a referral.
+    ///
+    /// A referral is a non-authoritative response without an answer but
+    /// with NS records for a zone above the query name in the authority
+    /// section.
+    #[allow(clippy::type_complexity)]
+    fn get_referral(
+        qname: &Name<Vec<u8>>,
+        msg: &Message<Bytes>,
+    ) -> Result<Option<(Name<Vec<u8>>, Vec<Name<Vec<u8>>>)>, Error> {
+        let header = msg.header();
+        if header.rcode() != Rcode::NOERROR
+            || header.aa()
+            || msg.header_counts().ancount() > 0
+        {
+            return Ok(None);
+        }
+
+        let mut zone: Option<Name<Vec<u8>>> = None;
+        let mut ns_set = Vec::new();
+        for record in msg.authority()?.limit_to_in::<Ns<_>>() {
+            let record = record?;
+            let owner: Name<Vec<u8>> = record.owner().to_name();
+            match zone.as_ref() {
+                None if qname.ends_with(&owner) => zone = Some(owner),
+                Some(zone) if *zone == owner => {}
+                _ => continue,
+            }
+            ns_set.push(record.data().nsdname().to_name());
+        }
+        Ok(zone.map(|zone| (zone, ns_set)))
+    }
+
+    /// Returns the servers for the name servers of a referral.
+    ///
+    /// Uses the glue records in the additional section of the referral
+    /// and looks up the addresses of name servers without glue.
+    async fn referral_servers(
+        &self,
+        msg: &Message<Bytes>,
+        ns_set: &[Name<Vec<u8>>],
+        resolv: &StubResolver,
+    ) -> Result<Vec<Server>, Error> {
+        let mut glue = HashMap::<Name<Vec<u8>>, Vec<IpAddr>>::new();
+        for record in msg.additional()?.into_records::<AllRecordData<_, _>>()
+        {
+            let Ok(record) = record else { continue };
+            let addr = match record.data() {
+                AllRecordData::A(a) => IpAddr::V4(a.addr()),
+                AllRecordData::Aaaa(aaaa) => IpAddr::V6(aaaa.addr()),
+                _ => continue,
+            };
+            glue.entry(record.owner().to_name()).or_default().push(addr);
+        }
+
+        let mut glueless = Vec::new();
+        let mut addrs = Vec::new();
+        for ns in ns_set {
+            match glue.get(ns) {
+                Some(ns_addrs) => addrs.extend_from_slice(ns_addrs),
+                None => glueless.push(ns.clone()),
+            }
+        }
+        if !glueless.is_empty() {
+            addrs.extend(get_ns_addrs(&glueless, resolv).await?);
+        }
+        Ok(self.servers_for(addrs))
+    }
+
+    /// Returns the servers for the given addresses on port 53.
+    ///
+    /// Honours the -4 and -6 options.
+    fn servers_for(&self, addrs: Vec<IpAddr>) -> Vec<Server> {
+        let mut seen = HashSet::new();
+        addrs
+            .into_iter()
+            .filter(|addr| {
+                !((addr.is_ipv4() && self.ipv6)
+                    || (addr.is_ipv6() && self.ipv4))
+            })
+            .filter(|addr| seen.insert(*addr))
+            .map(|addr| Server {
+                addr: SocketAddr::new(addr, 53),
+                transport: Transport::UdpTcp,
+                timeout: self.timeout(),
+                retries: self.retries(),
+                udp_payload_size: self.udp_payload_size(),
+                tls_hostname: None,
+                source: self.source,
+                proxy: self.proxy.clone(),
+                backoff: self.backoff(),
+            })
+            .collect()
+    }
+}
+
+/// The maximum number of referrals followed by --follow-referrals.
+const MAX_REFERRALS: usize = 16;
+
+/// # Tracing from the root
+///
+impl Query {
+    /// Resolves the query iteratively starting at the root servers.
+    ///
+    /// Every response along the way is printed. If the final answer is a
+    /// CNAME without the records of the query type for its target, the
+    /// trace starts over at the root for the target.
+    async fn trace(&self) -> Result<(), Error> {
+        let mut qname = self.qname().to_name();
+        let mut seen = HashSet::new();
+        loop {
+            if !seen.insert(qname.clone()) {
+                return Err(format!("CNAME loop at {}", qname).into());
+            }
+            if seen.len() > MAX_CNAMES {
+                return Err(format!("more than {} CNAMEs", MAX_CNAMES).into());
+            }
+            let servers = self.servers_for(
+                ROOT_SERVERS
+                    .iter()
+                    .map(|addr| IpAddr::from_str(addr).unwrap())
+                    .collect(),
+            );
+            println!(";; Asking the root servers for {}\n", qname);
+            let answer = Client::with_servers(servers)
+                .request(self.create_request_for(&qname)?)
+                .await?;
+            self.output.print(&answer)?;
+            let answer = self.follow_referrals(&qname, &answer).await?;
+            match self.cname_target(answer.message(), &qname, &mut seen)? {
+                Some(target) => {
+                    println!("\n;; Following CNAME to {}\n", target);
+                    qname = target;
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Returns the end of a CNAME chain that still needs resolving.
+    ///
+    /// Follows the CNAME records for `qname` in the answer section. Returns
+    /// `None` if there are none or the answer already contains records of
+    /// the query type for the end of the chain.
+    fn cname_target(
+        &self,
+        msg: &Message<Bytes>,
+        qname: &Name<Vec<u8>>,
+        seen: &mut HashSet<Name<Vec<u8>>>,
+    ) -> Result<Option<Name<Vec<u8>>>, Error> {
+        let qtype = self.qtype();
+        if qtype == Rtype::CNAME || qtype == Rtype::ANY {
+            return Ok(None);
+        }
+        let mut name = qname.clone();
+        let mut followed = false;
+        'chain: loop {
+            for record in msg.answer()?.limit_to_in::<Cname<_>>() {
+                let record = record?;
+                if *record.owner() == name {
+                    name = record.data().cname().to_name();
+                    if !seen.insert(name.clone()) {
+                        return Err(format!("CNAME loop at {}", name).into());
+                    }
+                    followed = true;
+                    continue 'chain;
+                }
+            }
+            break;
+        }
+        if !followed {
+            return Ok(None);
+        }
+        for record in msg.answer()? {
+            let record = record?;
+            if record.rtype() == qtype && *record.owner() == name {
+                return Ok(None);
+            }
+        }
+        // The caller inserts the target again when starting over.
+        seen.remove(&name);
+        Ok(Some(name))
+    }
+}
+
+/// The maximum number of CNAMEs followed by --trace.
+const MAX_CNAMES: usize = 16;
+
+/// The addresses of the root servers used by --trace.
+const ROOT_SERVERS: &[&str] = &[
+    "198.41.0.4",
+    "170.247.170.2",
+    "192.33.4.12",
+    "199.7.91.13",
+    "192.203.230.10",
+    "192.5.5.241",
+    "192.112.36.4",
+    "198.97.190.53",
+    "192.36.148.17",
+    "192.58.128.30",
+    "193.0.14.129",
+    "199.7.83.42",
+    "202.12.27.33",
+    "2001:503:ba3e::2:30",
+    "2801:1b8:10::b",
+    "2001:500:2::c",
+    "2001:500:2d::d",
+    "2001:500:a8::e",
+    "2001:500:2f::f",
+    "2001:500:12::d0d",
+    "2001:500:1::53",
+    "2001:7fe::53",
+    "2001:503:c27::2:30",
+    "2001:7fd::1",
+    "2001:500:9f::42",
+    "2001:dc3::35",
+];
+
+/// # Resolving targets
+///
+impl Query {
+    /// Prints the addresses of the targets of records in the answer.
+    ///
+    /// This considers SRV, MX, and NS records. Each target is only looked
+    /// up once.
+    async fn output_targets(
+        &self,
+        client: &Client,
+        answer: &Answer,
+    ) -> Result<(), Error> {
+        let mut addrs = HashMap::new();
+        let mut printed_header = false;
+        for record in
+            answer.message().answer()?.limit_to::<AllRecordData<_, _>>()
+        {
+            let record = record?;
+            let target: Name<Vec<u8>> = match record.data() {
+                AllRecordData::Srv(srv) => srv.target().to_name(),
+                AllRecordData::Mx(mx) => mx.exchange().to_name(),
+                AllRecordData::Ns(ns) => ns.nsdname().to_name(),
+                _ => continue,
+            };
+            // A target of "." means there is no service.
+            if target.is_root() {
+                continue;
+            }
+
+            if !printed_header {
+                println!("\n;; TARGET ADDRESSES:");
+                printed_header = true;
+            }
+            println!(
+                ";; {} {} {}",
+                record.owner(),
+                record.rtype(),
+                record.data()
+            );
+
+            if !addrs.contains_key(&target) {
+                let found = Self::lookup_target(client, &target).await;
+                addrs.insert(target.clone(), found);
+            }
+            match &addrs[&target] {
+                Ok(found) if found.is_empty() => {
+                    println!(";;     {} <no addresses found>", target);
+                }
+                Ok(found) => {
+                    for addr in found {
+                        println!(";;     {} {}", target, addr);
+                    }
+                }
+                Err(err) => println!(";;     {} <{}>", target, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the IPv4 and IPv6 addresses of a target.
+    async fn lookup_target(
+        client: &Client,
+        target: &Name<Vec<u8>>,
+    ) -> Result<Vec<IpAddr>, Error> {
+        let mut res: Vec<IpAddr> = Vec::new();
+        for rtype in [Rtype::A, Rtype::AAAA] {
+            let answer = client.query((target, rtype)).await?;
+            for record in
+                answer.message().answer()?.limit_to::<AllRecordData<_, _>>()
+            {
+                match record?.data() {
+                    AllRecordData::A(a) => res.push(a.addr().into()),
+                    AllRecordData::Aaaa(aaaa) => res.push(aaaa.addr().into()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// # Get an authoritative answer
+impl Query {
+    async fn auth_answer(&self) -> Result<Answer, Error> {
+        let servers = {
+            let resolver = StubResolver::new();
+            let apex = get_apex(&self.qname().to_name(), &resolver).await?;
+            let ns_set = get_ns_set(&apex, &resolver).await?;
+            self.get_ns_addrs(&ns_set, &resolver).await?
+        };
+        Client::with_servers(servers)
+            .query((self.qname().to_name(), self.qtype()))
+            .await
+    }
+
+    /// Returns the servers for all the addresses of the name servers.
+    async fn get_ns_addrs(
+        &self,
+        ns_set: &[Name<Vec<u8>>],
+        resolv: &StubResolver,
+    ) -> Result<Vec<Server>, Error> {
+        Ok(get_ns_addrs(ns_set, resolv)
+            .await?
+            .into_iter()
             .map(|addr| Server {
                 addr: SocketAddr::new(addr, 53),
                 transport: Transport::UdpTcp,
@@ -418,75 +2324,140 @@ impl Query {
                 retries: self.retries(),
                 udp_payload_size: self.udp_payload_size(),
                 tls_hostname: None,
+                source: self.source,
+                proxy: self.proxy.clone(),
+                backoff: self.backoff(),
             })
             .collect())
     }
 
     /// Produces a diff between two answer sections.
     ///
-    /// Returns `Ok(None)` if the two answer sections are identical apart from
-    /// the TTLs.
-    #[allow(clippy::mutable_key_type)]
+    /// The diff contains all records of both sections. TTLs are ignored.
     fn diff_answers(
         left: &Message<Bytes>,
         right: &Message<Bytes>,
-    ) -> Result<Option<Vec<DiffItem>>, Error> {
-        // Put all the answers into a two hashsets.
-        let left = left
-            .answer()?
-            .into_records::<AllRecordData<_, _>>()
-            .filter_map(Result::ok)
-            .map(|record| {
-                let class = record.class();
-                let (name, data) = record.into_owner_and_data();
-                (name, class, data)
-            })
-            .collect::<HashSet<_>>();
+    ) -> Result<Vec<DiffItem>, Error> {
+        Self::diff_section(left, right, Section::Answer, false)
+    }
 
-        let right = right
-            .answer()?
-            .into_records::<AllRecordData<_, _>>()
-            .filter_map(Result::ok)
-            .map(|record| {
-                let class = record.class();
-                let (name, data) = record.into_owner_and_data();
-                (name, class, data)
-            })
-            .collect::<HashSet<_>>();
+    /// Compares the records of one section of two messages.
+    ///
+    /// OPT records are left out since they describe the message rather
+    /// than the data. If `ttl` is true, records that only differ in their
+    /// TTL are reported as changed rather than unchanged.
+    #[allow(clippy::mutable_key_type)]
+    fn diff_section(
+        left: &Message<Bytes>,
+        right: &Message<Bytes>,
+        section: Section,
+        ttl: bool,
+    ) -> Result<Vec<DiffItem>, Error> {
+        let left = Self::section_records(left, section)?;
+        let right = Self::section_records(right, section)?;
 
         let mut diff = left
-            .intersection(&right)
-            .cloned()
-            .map(|item| (Action::Unchanged, item))
+            .iter()
+            .map(|(record, left_ttl)| {
+                let action = match right.get(record) {
+                    Some(right_ttl) if ttl && left_ttl != right_ttl => {
+                        Action::TtlChanged {
+                            from: *left_ttl,
+                            to: *right_ttl,
+                        }
+                    }
+                    Some(_) => Action::Unchanged,
+                    None => Action::Removed,
+                };
+                (action, record.clone())
+            })
             .collect::<Vec<_>>();
-        let size = diff.len();
-
-        diff.extend(
-            left.difference(&right)
-                .cloned()
-                .map(|item| (Action::Removed, item)),
-        );
 
         diff.extend(
             right
-                .difference(&left)
+                .keys()
+                .filter(|record| !left.contains_key(*record))
                 .cloned()
-                .map(|item| (Action::Added, item)),
+                .map(|record| (Action::Added, record)),
         );
 
         diff.sort_by(|left, right| left.1.cmp(&right.1));
 
-        if size == diff.len() {
-            Ok(None)
+        Ok(diff)
+    }
+
+    /// Returns the records of a section with their TTLs.
+    #[allow(clippy::mutable_key_type)]
+    fn section_records(
+        msg: &Message<Bytes>,
+        section: Section,
+    ) -> Result<HashMap<DiffRecord, Ttl>, Error> {
+        Ok(section
+            .records(msg)?
+            .into_records::<AllRecordData<_, _>>()
+            .filter_map(Result::ok)
+            .filter(|record| record.rtype() != Rtype::OPT)
+            .map(|record| {
+                let class = record.class();
+                let ttl = record.ttl();
+                let (name, data) = record.into_owner_and_data();
+                ((name, class, data), ttl)
+            })
+            .collect())
+    }
+
+    /// Compares the answer with that of an authoritative server.
+    ///
+    /// Only the answer section is compared unless `--verify-sections` is
+    /// given. Returns an error if any of the compared sections differ.
+    async fn output_verify(&self, answer: &Answer) -> Result<(), Error> {
+        let auth_answer = self.auth_answer().await?;
+        let sections = if self.verify_sections {
+            [Section::Answer, Section::Authority, Section::Additional]
+                .as_slice()
         } else {
-            Ok(Some(diff))
+            [Section::Answer].as_slice()
+        };
+        let mut mismatched = Vec::new();
+        for &section in sections {
+            let diff = Self::diff_section(
+                auth_answer.message(),
+                answer.message(),
+                section,
+                self.verify_ttl,
+            )?;
+            let summary = DiffSummary::new(&diff);
+            if summary.is_match() {
+                println!("\n;; Authoritative {} matches.", section);
+            } else {
+                println!("\n;; Authoritative {} does not match.", section);
+                println!(
+                    ";; Difference of {} with authoritative server {}:",
+                    section,
+                    auth_answer.stats().server_addr
+                );
+                self.output_diff(diff);
+                mismatched.push(section.to_string());
+            }
+            match section {
+                Section::Answer => println!(";; verify: {}", summary),
+                _ => println!(";; verify {}: {}", section, summary),
+            }
+        }
+        if !mismatched.is_empty() {
+            return Err(format!(
+                "authoritative {} does not match",
+                mismatched.join(", ")
+            )
+            .into());
         }
+        Ok(())
     }
 
     /// Prints the content of a diff.
     fn output_diff(&self, diff: Vec<DiffItem>) {
         for item in diff {
-            println!(
+            print!(
                 "{}{} {} {} {}",
                 item.0,
                 item.1 .0,
@@ -494,18 +2465,107 @@ impl Query {
                 item.1 .2.rtype(),
                 item.1 .2
             );
+            if let Action::TtlChanged { from, to } = item.0 {
+                print!(" ; TTL {} -> {}", from.as_secs(), to.as_secs());
+            }
+            println!();
+        }
+    }
+
+    /// Returns the name or address to look up.
+    ///
+    /// An address given via -x takes the place of the query name. Clap
+    /// makes sure that one of them is present.
+    fn qname(&self) -> NameOrAddr {
+        match (self.reverse, self.qname.as_ref()) {
+            (Some(addr), _) => NameOrAddr::Addr(addr),
+            (None, Some(qname)) => qname.clone(),
+            (None, None) => unreachable!("missing query name"),
+        }
+    }
+
+    /// Returns the record type of the query.
+    ///
+    /// If several types were given, this is the first one.
+    fn qtype(&self) -> Rtype {
+        self.qtypes().next().unwrap_or(match self.qname() {
+            NameOrAddr::Addr(_) => Rtype::PTR,
+            NameOrAddr::Name(_) => Rtype::AAAA,
+        })
+    }
+
+    /// Returns all record types given on the command line.
+    fn qtypes(&self) -> impl Iterator<Item = Rtype> + '_ {
+        self.qtype.iter().copied()
+    }
+}
+
+//------------ Authoritative Servers -----------------------------------------
+
+/// Tries to determine the apex of the zone the given name lives in.
+pub(super) async fn get_apex(
+    qname: &Name<Vec<u8>>,
+    resolv: &StubResolver,
+) -> Result<Name<Vec<u8>>, Error> {
+    // Ask for the SOA record for the qname.
+    let response = resolv.query((qname, Rtype::SOA)).await?;
+
+    // The SOA record is in the answer section if the qname is the apex
+    // or in the authority section with the apex as the owner name
+    // otherwise.
+    let mut answer = response.answer()?.limit_to_in::<Soa<_>>();
+    if let Some(soa) = answer.next() {
+        let soa = soa?;
+        if *soa.owner() == qname {
+            return Ok(qname.clone());
         }
+        // Strange SOA in the answer section, let’s continue with
+        // the authority section.
     }
 
-    fn qtype(&self) -> Rtype {
-        match self.qtype {
-            Some(qtype) => qtype,
-            None => match self.qname {
-                NameOrAddr::Addr(_) => Rtype::PTR,
-                NameOrAddr::Name(_) => Rtype::AAAA,
-            },
+    let mut authority =
+        answer.next_section()?.unwrap().limit_to_in::<Soa<_>>();
+    if let Some(soa) = authority.next() {
+        let soa = soa?;
+        return Ok(soa.owner().to_name());
+    }
+
+    Err("no SOA record".into())
+}
+
+/// Tries to find the NS set for the given apex name.
+pub(super) async fn get_ns_set(
+    apex: &Name<Vec<u8>>,
+    resolv: &StubResolver,
+) -> Result<Vec<Name<Vec<u8>>>, Error> {
+    let response = resolv.query((apex, Rtype::NS)).await?;
+    let mut res = Vec::new();
+    for record in response.answer()?.limit_to_in::<Ns<_>>() {
+        let record = record?;
+        if *record.owner() != apex {
+            continue;
+        }
+        res.push(record.data().nsdname().to_name());
+    }
+
+    // We could technically get the A and AAAA records from the additional
+    // section, but we’re going to ask anyway, so: meh.
+
+    Ok(res)
+}
+
+/// Tries to get all the addresses for all the name servers.
+pub(super) async fn get_ns_addrs(
+    ns_set: &[Name<Vec<u8>>],
+    resolv: &StubResolver,
+) -> Result<Vec<IpAddr>, Error> {
+    let mut res = HashSet::new();
+    for ns in ns_set {
+        for addr in resolv.lookup_host(ns).await?.iter() {
+            res.insert(addr);
         }
     }
+    Ok(res.into_iter().collect())
 }
 
 //------------ ServerName ---------------------------------------------------
@@ -530,6 +2590,224 @@ impl FromStr for ServerName {
     }
 }
 
+//------------ TransportArg --------------------------------------------------
+
+/// The transport as given via the --transport option.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum TransportArg {
+    /// UDP only.
+    Udp,
+
+    /// UDP, repeated over TCP if the response was truncated.
+    UdpTcp,
+
+    /// TCP only.
+    Tcp,
+
+    /// TLS.
+    Tls,
+
+    /// HTTPS.
+    Https,
+
+    /// QUIC.
+    Quic,
+}
+
+//------------ ServerSpec ----------------------------------------------------
+
+/// A server given on the command line, possibly including a port.
+#[derive(Clone, Debug)]
+struct ServerSpec {
+    name: ServerName,
+    port: Option<u16>,
+}
+
+impl FromStr for ServerSpec {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // An IPv6 address with a port needs to be in brackets.
+        if let Some(s) = s.strip_prefix('[') {
+            let (addr, port) =
+                s.split_once(']').ok_or("missing closing bracket")?;
+            let addr =
+                IpAddr::from_str(addr).map_err(|_| "illegal address")?;
+            let port = match port {
+                "" => None,
+                port => Some(parse_port(
+                    port.strip_prefix(':').ok_or("illegal port")?,
+                )?),
+            };
+            return Ok(ServerSpec {
+                name: ServerName::Addr(addr),
+                port,
+            });
+        }
+
+        // A bare IPv6 address contains colons, too, so check for that
+        // first.
+        if let Ok(addr) = IpAddr::from_str(s) {
+            return Ok(ServerSpec {
+                name: ServerName::Addr(addr),
+                port: None,
+            });
+        }
+        match s.rsplit_once(':') {
+            Some((name, port)) => Ok(ServerSpec {
+                name: ServerName::from_str(name)?,
+                port: Some(parse_port(port)?),
+            }),
+            None => Ok(ServerSpec {
+                name: ServerName::from_str(s)?,
+                port: None,
+            }),
+        }
+    }
+}
+
+impl ServerSpec {
+    /// Returns whether both specs name the same server and port.
+    fn is_same(&self, other: &Self) -> bool {
+        self.port == other.port
+            && match (&self.name, &other.name) {
+                (ServerName::Addr(left), ServerName::Addr(right)) => {
+                    left == right
+                }
+                (ServerName::Name(left), ServerName::Name(right)) => {
+                    left.to_string().eq_ignore_ascii_case(&right.to_string())
+                }
+                _ => false,
+            }
+    }
+}
+
+/// Parses a source address with an optional port.
+///
+/// Without a port, the system picks one.
+fn parse_source(s: &str) -> Result<SocketAddr, &'static str> {
+    SocketAddr::from_str(s)
+        .or_else(|_| IpAddr::from_str(s).map(|addr| SocketAddr::new(addr, 0)))
+        .map_err(|_| "illegal source address")
+}
+
+/// Parses an algorithm number for the DAU, DHU, and N3U options.
+///
+/// Zero is reserved in all three registries and therefore rejected.
+fn parse_algorithm(s: &str) -> Result<u8, &'static str> {
+    match u8::from_str(s.trim()) {
+        Ok(alg) if alg != 0 => Ok(alg),
+        _ => Err("expected an algorithm number between 1 and 255"),
+    }
+}
+
+/// Parses the factor the backoff between retries grows by.
+///
+/// The factor must be at least one so the backoff doesn’t shrink.
+fn parse_backoff_factor(s: &str) -> Result<f32, &'static str> {
+    match f32::from_str(s) {
+        Ok(factor) if (1. ..=100.).contains(&factor) => Ok(factor),
+        _ => Err("expected a number between 1 and 100"),
+    }
+}
+
+/// Parses a port given either as a number or a well-known service name.
+fn parse_port(s: &str) -> Result<u16, &'static str> {
+    match s {
+        "domain" => Ok(53),
+        "domain-s" => Ok(853),
+        _ => u16::from_str(s).map_err(|_| "illegal port"),
+    }
+}
+
+/// Parses the value for the reserved bits of the header given in hex.
+///
+/// Only one reserved bit is left in the header since AD and CD took over
+/// the other two, so this only accepts 0 and 1.
+fn parse_z_bits(s: &str) -> Result<u8, &'static str> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    match u8::from_str_radix(s, 16) {
+        Ok(value) if value <= 1 => Ok(value),
+        Ok(_) => Err("there is only one reserved bit, use 0 or 1"),
+        Err(_) => Err("illegal hex value"),
+    }
+}
+
+/// Parses a record type given by its mnemonic or the generic TYPEnnn form.
+///
+/// The generic form allows querying for types without a mnemonic, such as
+/// private use types.
+pub(super) fn parse_rtype(s: &str) -> Result<Rtype, &'static str> {
+    if let Ok(rtype) = Rtype::from_str(s) {
+        return Ok(rtype);
+    }
+    s.get(..4)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("TYPE"))
+        .and_then(|_| u16::from_str(&s[4..]).ok())
+        .map(Rtype::from_int)
+        .ok_or("unknown record type")
+}
+
+/// Parses a class given by its mnemonic, generic form, or as a number.
+fn parse_class(s: &str) -> Result<Class, &'static str> {
+    Class::from_str(s)
+        .ok()
+        .or_else(|| u16::from_str(s).ok().map(Class::from_int))
+        .ok_or("unknown class")
+}
+
+/// Parses an opcode given either by its mnemonic or as a number.
+fn parse_opcode(s: &str) -> Result<Opcode, &'static str> {
+    Ok(match s.to_ascii_uppercase().as_str() {
+        "QUERY" => Opcode::QUERY,
+        "IQUERY" => Opcode::IQUERY,
+        "STATUS" => Opcode::STATUS,
+        "NOTIFY" => Opcode::NOTIFY,
+        "UPDATE" => Opcode::UPDATE,
+        s => match u8::from_str(s) {
+            Ok(value) if value < 16 => Opcode::from_int(value),
+            _ => return Err("unknown opcode"),
+        },
+    })
+}
+
+/// Randomizes the case of the letters of a name for 0x20 encoding.
+///
+/// Length octets are never letters, so the wire format can be changed as
+/// is. The case of each letter is picked by a splitmix64 sequence started
+/// at `seed`, so the same seed always results in the same name.
+fn randomize_case(name: &Name<Vec<u8>>, seed: u64) -> Name<Vec<u8>> {
+    let mut state = seed;
+    let mut bits = 0u64;
+    let mut left = 0;
+    let octets = name
+        .as_slice()
+        .iter()
+        .map(|&ch| {
+            if !ch.is_ascii_alphabetic() {
+                return ch;
+            }
+            if left == 0 {
+                state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                let mut z = state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                bits = z ^ (z >> 31);
+                left = 64;
+            }
+            let upper = bits & 1 == 1;
+            bits >>= 1;
+            left -= 1;
+            if upper {
+                ch.to_ascii_uppercase()
+            } else {
+                ch.to_ascii_lowercase()
+            }
+        })
+        .collect::<Vec<_>>();
+    Name::from_octets(octets).expect("changing case keeps names valid")
+}
+
 //------------ NameOrAddr ----------------------------------------------------
 
 #[derive(Clone, Debug)]
@@ -547,6 +2825,30 @@ impl NameOrAddr {
             }
         }
     }
+
+    /// Checks that the name is a valid host name.
+    ///
+    /// Host names may only consist of letters, digits, and hyphens and
+    /// labels must not start or end with a hyphen, as per RFC 952 and
+    /// RFC 1123. Addresses are always fine.
+    fn check_hostname(&self) -> Result<(), String> {
+        let NameOrAddr::Name(name) = self else {
+            return Ok(());
+        };
+        for label in name.iter_labels().filter(|label| !label.is_root()) {
+            let octets = label.as_slice();
+            let ldh = octets
+                .iter()
+                .all(|ch| ch.is_ascii_alphanumeric() || *ch == b'-');
+            if !ldh || octets.starts_with(b"-") || octets.ends_with(b"-") {
+                return Err(format!(
+                    "'{}' is not a valid host name: illegal label '{}'",
+                    name, label
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl FromStr for NameOrAddr {
@@ -563,6 +2865,212 @@ impl FromStr for NameOrAddr {
     }
 }
 
+//------------ HexData -------------------------------------------------------
+
+/// Binary data given on the command line as a hex string.
+#[derive(Clone, Debug)]
+pub(super) struct HexData(pub(super) Vec<u8>);
+
+impl FromStr for HexData {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() % 2 != 0 {
+            return Err("odd number of hex digits");
+        }
+        if s.len() / 2 > usize::from(u16::MAX) {
+            return Err("too much data");
+        }
+        s.as_bytes()
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or("illegal hex digit")
+            })
+            .collect::<Result<_, _>>()
+            .map(HexData)
+    }
+}
+
+impl fmt::Display for HexData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ch in &self.0 {
+            write!(f, "{:02x}", ch)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ RawOpt --------------------------------------------------------
+
+/// An EDNS option given on the command line as code and hex data.
+#[derive(Clone, Debug)]
+struct RawOpt {
+    code: OptionCode,
+    data: HexData,
+}
+
+impl FromStr for RawOpt {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, data) = s.split_once(':').unwrap_or((s, ""));
+        let code = u16::from_str(code).map_err(|_| "illegal option code")?;
+        Ok(RawOpt {
+            code: OptionCode::from_int(code),
+            data: HexData::from_str(data)?,
+        })
+    }
+}
+
+//------------ Subnet --------------------------------------------------------
+
+/// A client subnet given on the command line.
+///
+/// The format is the address, the source prefix length, and optionally the
+/// scope prefix length, all separated by slashes. Bits of the address
+/// beyond the source prefix are cleared.
+#[derive(Clone, Copy, Debug)]
+struct Subnet {
+    addr: IpAddr,
+    source_prefix: u8,
+    scope_prefix: u8,
+}
+
+impl FromStr for Subnet {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        let addr = parts
+            .next()
+            .and_then(|addr| IpAddr::from_str(addr).ok())
+            .ok_or("illegal address")?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let mut prefix_len = |default| match parts.next() {
+            Some(len) => u8::from_str(len)
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or("illegal prefix length"),
+            None => Ok(default),
+        };
+        let source_prefix = prefix_len(max_len)?;
+        let scope_prefix = prefix_len(0)?;
+        if parts.next().is_some() {
+            return Err("too many slashes");
+        }
+        let addr = match addr {
+            IpAddr::V4(addr) => IpAddr::V4(Ipv4Addr::from(
+                u32::from(addr)
+                    & u32::MAX
+                        .checked_shl(32 - u32::from(source_prefix))
+                        .unwrap_or(0),
+            )),
+            IpAddr::V6(addr) => IpAddr::V6(Ipv6Addr::from(
+                u128::from(addr)
+                    & u128::MAX
+                        .checked_shl(128 - u32::from(source_prefix))
+                        .unwrap_or(0),
+            )),
+        };
+        Ok(Subnet {
+            addr,
+            source_prefix,
+            scope_prefix,
+        })
+    }
+}
+
+//------------ TsigSpec ------------------------------------------------------
+
+/// A TSIG key given on the command line.
+///
+/// The format is the key name, the algorithm, and the Base 64 encoded
+/// secret separated by colons.
+#[derive(Clone, Debug)]
+struct TsigSpec {
+    name: tsig::KeyName,
+    algorithm: tsig::Algorithm,
+    secret: Vec<u8>,
+}
+
+impl TsigSpec {
+    /// Creates the key for signing.
+    fn key(&self) -> Result<tsig::Key, Error> {
+        tsig::Key::new(
+            self.algorithm,
+            &self.secret,
+            self.name.clone(),
+            None,
+            None,
+        )
+        .map_err(|err| Error::config(format!("invalid TSIG key: {}", err)))
+    }
+}
+
+impl FromStr for TsigSpec {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(name), Some(algorithm), Some(secret)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("expected NAME:ALGORITHM:SECRET");
+        };
+        let algorithm = match algorithm.to_ascii_lowercase().as_str() {
+            "hmac-sha1" => tsig::Algorithm::Sha1,
+            "hmac-sha256" => tsig::Algorithm::Sha256,
+            "hmac-sha384" => tsig::Algorithm::Sha384,
+            "hmac-sha512" => tsig::Algorithm::Sha512,
+            _ => return Err("unsupported TSIG algorithm"),
+        };
+        Ok(TsigSpec {
+            name: tsig::KeyName::from_str(name)
+                .map_err(|_| "illegal TSIG key name")?,
+            algorithm,
+            secret: base64::decode(secret)
+                .map_err(|_| "illegal Base 64 in TSIG secret")?,
+        })
+    }
+}
+
+//------------ Section -------------------------------------------------------
+
+/// A section of a message holding records.
+#[derive(Clone, Copy, Debug)]
+enum Section {
+    Answer,
+    Authority,
+    Additional,
+}
+
+impl Section {
+    /// Returns the records of this section in the message.
+    fn records(
+        self,
+        msg: &Message<Bytes>,
+    ) -> Result<RecordSection<'_, Bytes>, Error> {
+        Ok(match self {
+            Self::Answer => msg.answer()?,
+            Self::Authority => msg.authority()?,
+            Self::Additional => msg.additional()?,
+        })
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Answer => "ANSWER",
+            Self::Authority => "AUTHORITY",
+            Self::Additional => "ADDITIONAL",
+        })
+    }
+}
+
 //------------ Action --------------------------------------------------------
 
 #[derive(Clone, Copy, Debug)]
@@ -570,6 +3078,12 @@ enum Action {
     Added,
     Removed,
     Unchanged,
+
+    /// The record is on both sides but with a different TTL.
+    TtlChanged {
+        from: Ttl,
+        to: Ttl,
+    },
 }
 
 impl fmt::Display for Action {
@@ -578,17 +3092,355 @@ impl fmt::Display for Action {
             Self::Added => "+ ",
             Self::Removed => "- ",
             Self::Unchanged => "  ",
+            Self::TtlChanged { .. } => "~ ",
         })
     }
 }
 
+//----------- DiffSummary ----------------------------------------------------
+
+/// The number of records for each action in a diff.
+#[derive(Clone, Copy, Debug, Default)]
+struct DiffSummary {
+    added: usize,
+    removed: usize,
+    unchanged: usize,
+    ttl_changed: usize,
+}
+
+impl DiffSummary {
+    fn new(diff: &[DiffItem]) -> Self {
+        let mut res = Self::default();
+        for item in diff {
+            match item.0 {
+                Action::Added => res.added += 1,
+                Action::Removed => res.removed += 1,
+                Action::Unchanged => res.unchanged += 1,
+                Action::TtlChanged { .. } => res.ttl_changed += 1,
+            }
+        }
+        res
+    }
+
+    /// Returns whether both sides of the diff are the same.
+    fn is_match(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.ttl_changed == 0
+    }
+}
+
+impl fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} unchanged",
+            self.added, self.removed, self.unchanged
+        )?;
+        if self.ttl_changed > 0 {
+            write!(f, ", {} TTL changed", self.ttl_changed)?;
+        }
+        Ok(())
+    }
+}
+
 //----------- DiffItem -------------------------------------------------------
 
-type DiffItem = (
-    Action,
-    (
-        ParsedName<Bytes>,
-        Class,
-        AllRecordData<Bytes, ParsedName<Bytes>>,
-    ),
+type DiffItem = (Action, DiffRecord);
+
+/// A record compared in a diff without its TTL.
+type DiffRecord = (
+    ParsedName<Bytes>,
+    Class,
+    AllRecordData<Bytes, ParsedName<Bytes>>,
 );
+
+//------------ LatencyStats --------------------------------------------------
+
+/// The collected outcome of repeatedly sending the same request.
+#[derive(Clone, Debug, Default)]
+struct LatencyStats {
+    /// The round-trip times of all answered requests.
+    durations: Vec<TimeDelta>,
+
+    /// The number of answers for each rcode.
+    rcodes: BTreeMap<String, usize>,
+
+    /// The number of failed requests for each error.
+    errors: BTreeMap<String, usize>,
+}
+
+impl LatencyStats {
+    /// Adds the outcome of a single request.
+    fn add(&mut self, res: Result<(TimeDelta, Rcode), Error>) {
+        match res {
+            Ok((duration, rcode)) => {
+                self.durations.push(duration);
+                *self.rcodes.entry(rcode.to_string()).or_default() += 1;
+            }
+            Err(err) => {
+                *self.errors.entry(err.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Prints the statistics.
+    fn print(&mut self) {
+        let failed = self.errors.values().sum::<usize>();
+        println!(
+            ";; {} queries, {} answered, {} failed",
+            self.durations.len() + failed,
+            self.durations.len(),
+            failed
+        );
+        if !self.durations.is_empty() {
+            self.durations.sort();
+            let len = self.durations.len();
+            let mean = self.durations.iter().map(Self::millis).sum::<f64>()
+                / len as f64;
+            let median = if len % 2 == 0 {
+                (Self::millis(&self.durations[len / 2 - 1])
+                    + Self::millis(&self.durations[len / 2]))
+                    / 2.
+            } else {
+                Self::millis(&self.durations[len / 2])
+            };
+            // Nearest-rank percentile.
+            let p95 =
+                Self::millis(&self.durations[(len * 95).div_ceil(100) - 1]);
+            println!(
+                ";; Time: min {:.3} ms, max {:.3} ms, mean {:.3} ms, \
+                 median {:.3} ms, p95 {:.3} ms",
+                Self::millis(&self.durations[0]),
+                Self::millis(&self.durations[len - 1]),
+                mean,
+                median,
+                p95
+            );
+        }
+        for (rcode, count) in &self.rcodes {
+            println!(";; {}: {}", rcode, count);
+        }
+        for (err, count) in &self.errors {
+            println!(";; Error '{}': {}", err, count);
+        }
+    }
+
+    /// Returns a duration in fractional milliseconds.
+    fn millis(duration: &TimeDelta) -> f64 {
+        duration
+            .num_microseconds()
+            .map(|micros| micros as f64 / 1000.)
+            .unwrap_or(f64::INFINITY)
+    }
+}
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    /// A command line with only the arguments of the query command.
+    #[derive(clap::Parser)]
+    struct Cmd {
+        #[command(flatten)]
+        query: Query,
+    }
+
+    /// Parses the arguments of the query command.
+    fn query(args: &[&str]) -> Query {
+        Cmd::parse_from(["dnsi"].iter().chain(args)).query
+    }
+
+    /// Returns the length of the request for the given arguments.
+    fn request_len(args: &[&str]) -> Result<usize, Error> {
+        Ok(query(args).create_request()?.to_vec()?.len())
+    }
+
+    /// Starts a server that answers one request with TC set if needed.
+    ///
+    /// The server pretends the full answer needs 1232 octets. Returns the
+    /// address the server listens on.
+    fn truncating_server() -> SocketAddr {
+        let sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = sock.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 2048];
+            let (len, peer) = sock.recv_from(&mut buf).unwrap();
+            let request = Message::from_octets(&buf[..len]).unwrap();
+            let size = request
+                .opt()
+                .map_or(MIN_UDP_PAYLOAD_SIZE, |opt| opt.udp_payload_size());
+            let mut msg = MessageBuilder::new_vec()
+                .start_answer(&request, Rcode::NOERROR)
+                .unwrap();
+            msg.header_mut().set_tc(size < 1232);
+            sock.send_to(&msg.finish(), peer).unwrap();
+        });
+        addr
+    }
+
+    /// Returns whether a UDP response with the given buffer size has TC.
+    fn truncated(udp_payload_size: &str) -> bool {
+        let server = truncating_server().to_string();
+        let query = query(&[
+            "example.com",
+            "--udp",
+            "--udp-payload-size",
+            udp_payload_size,
+            "--server",
+            &server,
+        ]);
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let client = query.client().await.unwrap();
+                let answer = query.request(&client).await.unwrap();
+                answer.message().header().tc()
+            })
+    }
+
+    #[test]
+    fn small_buffer_truncates() {
+        assert!(truncated("512"));
+        assert!(!truncated("1232"));
+    }
+
+    #[test]
+    fn env_server() {
+        std::env::set_var("DNSI_SERVER", "192.0.2.1");
+        let trace = Cmd::try_parse_from(["dnsi", "--trace", "example.com"]);
+        let trace_specs = query(&["--trace", "example.com"]).server_specs();
+        let specs = query(&["example.com"]).server_specs();
+        let at_specs = query(&["--at-server", "192.0.2.2", "example.com"])
+            .server_specs();
+        #[cfg(unix)]
+        let fd = Cmd::try_parse_from(["dnsi", "--fd", "3", "example.com"]);
+        std::env::remove_var("DNSI_SERVER");
+
+        // The variable doesn’t count as --server given on the command
+        // line and thus doesn’t conflict with --trace or --fd.
+        assert!(trace.is_ok());
+        assert!(trace_specs.unwrap().is_empty());
+        #[cfg(unix)]
+        assert!(fd.unwrap().query.server_specs().unwrap().is_empty());
+        let specs = specs.unwrap();
+        let expected = ServerSpec::from_str("192.0.2.1").unwrap();
+        assert_eq!(specs.len(), 1);
+        assert!(specs[0].is_same(&expected));
+
+        // A server given via @ overrides the variable.
+        let at_specs = at_specs.unwrap();
+        let expected = ServerSpec::from_str("192.0.2.2").unwrap();
+        assert_eq!(at_specs.len(), 1);
+        assert!(at_specs[0].is_same(&expected));
+    }
+
+    #[test]
+    fn comparison_tls_port() {
+        let port = |args: &[&str], port| {
+            let mut args = args.to_vec();
+            args.push("example.com");
+            query(&args).comparison_tls_port(port)
+        };
+        assert_eq!(port(&["--https", "-s", "192.0.2.1"], 443), 853);
+        assert_eq!(port(&["--https", "-s", "192.0.2.1:8443"], 8443), 853);
+        assert_eq!(port(&["--quic", "-s", "192.0.2.1"], 853), 853);
+        assert_eq!(port(&["--quic", "-s", "192.0.2.1:8853"], 8853), 853);
+        assert_eq!(port(&["--tls", "-s", "192.0.2.1"], 853), 853);
+        assert_eq!(port(&["--tls", "-s", "192.0.2.1:8853"], 8853), 8853);
+        assert_eq!(
+            port(&["--tls", "-s", "192.0.2.1", "-p", "8853"], 8853),
+            8853
+        );
+        assert_eq!(port(&["-s", "192.0.2.1:5353"], 5353), 853);
+    }
+
+    #[test]
+    fn reverse_ipv6() {
+        let query = query(&["-x", "2001:db8::1"]);
+        assert_eq!(
+            query.qname().to_name(),
+            Name::<Vec<u8>>::from_str(
+                "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.\
+                 0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+            )
+            .unwrap()
+        );
+        assert_eq!(query.qtype(), Rtype::PTR);
+    }
+
+    #[test]
+    fn reverse_ipv4() {
+        let query = query(&["-x", "192.0.2.1"]);
+        assert_eq!(
+            query.qname().to_name(),
+            Name::<Vec<u8>>::from_str("1.2.0.192.in-addr.arpa").unwrap()
+        );
+        assert_eq!(query.qtype(), Rtype::PTR);
+    }
+
+    /// Returns the message ID of the request for the given arguments.
+    fn request_id(args: &[&str]) -> u16 {
+        query(args).create_request().unwrap().header().id()
+    }
+
+    #[test]
+    fn random_id() {
+        // Eight equal random IDs in a row are too unlikely to happen.
+        let ids = (0..8)
+            .map(|_| request_id(&["example.com"]))
+            .collect::<Vec<_>>();
+        assert!(ids.iter().any(|id| *id != ids[0]), "{:?}", ids);
+    }
+
+    #[test]
+    fn fixed_id() {
+        for _ in 0..8 {
+            assert_eq!(request_id(&["example.com", "--id", "1234"]), 1234);
+        }
+    }
+
+    #[test]
+    fn padding_to_block() {
+        for args in [
+            &["example.com", "--padding"][..],
+            &["example.com", "--edns-padding-response-check"],
+            &["www.example.com", "AAAA", "--padding", "--nsid"],
+            &["example.com", "--padding", "--ednsopt", "65001:00ff00"],
+        ] {
+            let len = request_len(args).unwrap();
+            assert_eq!(len % REQUEST_PADDING_BLOCK, 0, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn padding_description() {
+        let check = |args: &[&str]| {
+            let mut args = [&["example.com"], args].concat();
+            args.push("--edns-padding-response-check");
+            query(&args).request_padding()
+        };
+        assert_eq!(check(&[]), "request padded to a multiple of 128 octets");
+        assert_eq!(
+            check(&["--padding"]),
+            "request padded to a multiple of 128 octets"
+        );
+        assert_eq!(check(&["--padding=20"]), "request padded with 20 octets");
+    }
+
+    #[test]
+    fn padding_too_long() {
+        assert!(request_len(&["example.com", "--padding=65000"]).is_ok());
+        assert!(request_len(&["example.com", "--padding=65535"]).is_err());
+        assert!(request_len(&[
+            "example.com",
+            "--padding=65500",
+            "--ednsopt",
+            "65001:00ff00"
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! The soa-check command of _dnsi._
+
+use super::query::{get_apex, get_ns_addrs, get_ns_set};
+use crate::client::{Answer, Client, Server, Transport};
+use crate::error::Error;
+use crate::output::{Color, Highlight, TableWriter};
+use domain::base::iana::Rtype;
+use domain::base::name::Name;
+use domain::rdata::Soa;
+use domain::resolv::stub::StubResolver;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+//------------ SoaCheck ------------------------------------------------------
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct SoaCheck {
+    /// The zone to check
+    #[arg(value_name = "ZONE")]
+    zone: Name<Vec<u8>>,
+
+    /// Set the timeout for each query in seconds.
+    #[arg(long, value_name = "SECONDS", default_value = "5")]
+    timeout: f32,
+
+    /// When to highlight the output with ANSI codes.
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    color: Color,
+}
+
+/// # Executing the command
+///
+impl SoaCheck {
+    pub fn execute(self) -> Result<(), Error> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(self.async_execute())
+    }
+
+    pub async fn async_execute(self) -> Result<(), Error> {
+        let resolver = StubResolver::new();
+        let apex = get_apex(&self.zone, &resolver).await?;
+        let ns_set = get_ns_set(&apex, &resolver).await?;
+        let mut addrs = get_ns_addrs(&ns_set, &resolver).await?;
+        if addrs.is_empty() {
+            return Err(format!(
+                "no addresses for the name servers of {}",
+                apex
+            )
+            .into());
+        }
+        addrs.sort();
+
+        // Query all servers at the same time but keep the results in the
+        // order of their addresses.
+        let queries = addrs
+            .into_iter()
+            .map(|addr| {
+                let client = self.client(addr);
+                let apex = apex.clone();
+                let query = tokio::spawn(async move {
+                    client.query((apex, Rtype::SOA)).await
+                });
+                (addr, query)
+            })
+            .collect::<Vec<_>>();
+        let mut results = Vec::new();
+        for (addr, query) in queries {
+            let answer = match query.await {
+                Ok(answer) => answer,
+                Err(err) => Err(err.to_string().into()),
+            };
+            results.push(SoaResult::new(addr, &apex, answer));
+        }
+
+        let majority = Self::majority_serial(&results);
+        let rows = results
+            .iter()
+            .map(|result| result.row(majority))
+            .collect::<Vec<_>>();
+        let highlights = results
+            .iter()
+            .map(|result| result.highlight(majority))
+            .collect::<Vec<_>>();
+
+        println!("SOA serials for {}", apex);
+        self.color.apply();
+        TableWriter {
+            indent: "  ",
+            header: Some(["Server", "Serial", "Rcode", "Time", "Note"]),
+            rows: &rows,
+            right_aligned: [false, true, false, true, false],
+            highlights: &highlights,
+            ..Default::default()
+        }
+        .write(std::io::stdout().lock())
+        .map_err(Error::output)?;
+
+        if results.iter().all(|result| {
+            result.serial.is_some() && result.serial == majority
+        }) {
+            Ok(())
+        } else {
+            Err("not all name servers agree on the SOA serial".into())
+        }
+    }
+}
+
+/// # Helpers
+///
+impl SoaCheck {
+    /// Returns a client that only asks the server at the given address.
+    fn client(&self, addr: IpAddr) -> Client {
+        Client::with_servers(vec![Server {
+            addr: SocketAddr::new(addr, 53),
+            transport: Transport::UdpTcp,
+            timeout: Duration::from_secs_f32(self.timeout),
+            retries: 2,
+            udp_payload_size: 1232,
+            tls_hostname: None,
+            source: None,
+            proxy: None,
+            backoff: None,
+        }])
+    }
+
+    /// Returns the serial reported by most servers.
+    ///
+    /// If there is a tie, the highest serial wins.
+    fn majority_serial(results: &[SoaResult]) -> Option<u32> {
+        let mut counts = HashMap::new();
+        for serial in results.iter().filter_map(|result| result.serial) {
+            *counts.entry(serial).or_insert(0usize) += 1;
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(serial, count)| (*count, *serial))
+            .map(|(serial, _)| serial)
+    }
+}
+
+//------------ SoaResult -----------------------------------------------------
+
+/// The outcome of asking a single server for the SOA record.
+#[derive(Clone, Debug)]
+struct SoaResult {
+    addr: IpAddr,
+    serial: Option<u32>,
+    rcode: Option<String>,
+    time: Option<i64>,
+    error: Option<String>,
+}
+
+impl SoaResult {
+    fn new(
+        addr: IpAddr,
+        apex: &Name<Vec<u8>>,
+        answer: Result<Answer, Error>,
+    ) -> Self {
+        let answer = match answer {
+            Ok(answer) => answer,
+            Err(err) => {
+                return SoaResult {
+                    addr,
+                    serial: None,
+                    rcode: None,
+                    time: None,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+        let serial = answer.message().answer().ok().and_then(|section| {
+            section
+                .limit_to_in::<Soa<_>>()
+                .filter_map(Result::ok)
+                .find(|record| *record.owner() == apex)
+                .map(|record| record.data().serial().into_int())
+        });
+        SoaResult {
+            addr,
+            serial,
+            rcode: Some(answer.message().header().rcode().to_string()),
+            time: Some(answer.stats().duration.num_milliseconds()),
+            error: None,
+        }
+    }
+
+    /// Returns the table row for the result.
+    fn row(&self, majority: Option<u32>) -> [String; 5] {
+        let note = match (&self.error, self.serial) {
+            (Some(err), _) => err.clone(),
+            (None, None) => "no SOA record".into(),
+            (None, serial) if serial != majority => "serial differs".into(),
+            _ => String::new(),
+        };
+        [
+            self.addr.to_string(),
+            self.serial
+                .map(|serial| serial.to_string())
+                .unwrap_or_else(|| "-".into()),
+            self.rcode.clone().unwrap_or_else(|| "-".into()),
+            self.time
+                .map(|time| format!("{} ms", time))
+                .unwrap_or_else(|| "-".into()),
+            note,
+        ]
+    }
+
+    /// Returns how the table row for the result is highlighted.
+    ///
+    /// Failed servers are shown as errors, those that disagree with the
+    /// majority as warnings.
+    fn highlight(&self, majority: Option<u32>) -> Highlight {
+        if self.error.is_some() || self.serial.is_none() {
+            Highlight::Error
+        } else if self.serial != majority {
+            Highlight::Warning
+        } else {
+            Highlight::None
+        }
+    }
+}
@@ -0,0 +1,1178 @@
+//! The validate command of _dnsi._
+
+use super::query::{parse_rtype, HexData};
+use crate::client::{Client, Server, Transport};
+use crate::error::Error;
+use bytes::Bytes;
+use domain::base::iana::{DigestAlg, Nsec3HashAlg, Rcode, Rtype, SecAlg};
+use domain::base::message::Message;
+use domain::base::message_builder::MessageBuilder;
+use domain::base::name::{Name, ParsedName, ToName};
+use domain::base::rdata::RecordData;
+use domain::base::record::Record;
+use domain::base::Serial;
+use domain::net::client::request::RequestMessage;
+use domain::rdata::dnssec::Timestamp;
+use domain::rdata::nsec3::OwnerHash;
+use domain::rdata::{AllRecordData, Dnskey, Nsec, Nsec3, Rrsig};
+use domain::validate::{DnskeyExt, RrsigExt};
+use ring::digest;
+use std::cmp::Ordering;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A record as parsed from a response.
+type ParsedRecord =
+    Record<ParsedName<Bytes>, AllRecordData<Bytes, ParsedName<Bytes>>>;
+
+/// A signature as parsed from a response.
+type ParsedRrsig = Rrsig<Bytes, ParsedName<Bytes>>;
+
+/// The DS records of the IANA root zone KSKs.
+const ROOT_ANCHORS: [&str; 2] = [
+    ". DS 20326 8 2 \
+     E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    ". DS 38696 8 2 \
+     683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+];
+
+/// The maximum number of CNAMEs followed.
+const MAX_CNAMES: usize = 16;
+
+/// The largest number of NSEC3 hash iterations we are willing to do.
+///
+/// This is the limit many validators use, see RFC 9276.
+const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+//------------ Validate ------------------------------------------------------
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct Validate {
+    /// The name of the records to validate
+    #[arg(value_name = "QUERY_NAME")]
+    qname: Name<Vec<u8>>,
+
+    /// The record type to validate
+    #[arg(
+        value_name = "QUERY_TYPE",
+        default_value = "A",
+        value_parser = parse_rtype
+    )]
+    qtype: Rtype,
+
+    /// The server to send queries to. System servers used if missing
+    #[arg(short, long, value_name = "ADDR")]
+    server: Option<IpAddr>,
+
+    /// Set the timeout for each query in seconds.
+    #[arg(long, value_name = "SECONDS", default_value = "5")]
+    timeout: f32,
+
+    /// Use this DS record as trust anchor instead of the root zone’s.
+    #[arg(long, value_name = "DS_RECORD")]
+    anchor: Vec<TrustAnchor>,
+}
+
+/// # Executing the command
+///
+impl Validate {
+    pub fn execute(self) -> Result<(), Error> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(self.async_execute())
+    }
+
+    pub async fn async_execute(self) -> Result<(), Error> {
+        let client = self.client();
+        let mut qname = self.qname.clone();
+        let mut rrsets = Vec::new();
+        let mut insecure = None;
+        loop {
+            let (rrset, cut) = self.validate_rrset(&client, &qname).await?;
+            let target = match rrset.first().map(|record| record.data()) {
+                Some(AllRecordData::Cname(cname))
+                    if self.qtype != Rtype::CNAME =>
+                {
+                    Some(cname.cname().to_name::<Vec<u8>>())
+                }
+                _ => None,
+            };
+            rrsets.push(rrset);
+            if insecure.is_none() {
+                insecure = cut;
+            }
+            let Some(target) = target else { break };
+            if rrsets.len() > MAX_CNAMES {
+                return Err(Self::bogus(format!(
+                    "more than {} CNAMEs",
+                    MAX_CNAMES
+                )));
+            }
+            println!(";; Following CNAME to {}\n", target);
+            qname = target;
+        }
+
+        match insecure {
+            Some(cut) => println!(
+                "\n;; insecure: {} is delegated without DS records",
+                cut
+            ),
+            None => println!("\n;; secure: fully validated"),
+        }
+        for rrset in &rrsets {
+            Self::output_rrset(rrset);
+        }
+        Ok(())
+    }
+
+    /// Validates the RRset of the query type or a CNAME for `qname`.
+    ///
+    /// Returns the RRset and, if it is insecure, the delegation without DS
+    /// records it is below. Reports why validation failed and returns an
+    /// error if it isn’t secure or insecure.
+    async fn validate_rrset(
+        &self,
+        client: &Client,
+        qname: &Name<Vec<u8>>,
+    ) -> Result<(Vec<ParsedRecord>, Option<Name<Vec<u8>>>), Error> {
+        let msg = Self::query(client, qname, self.qtype).await?;
+        let rcode = msg.header().rcode();
+        if rcode != Rcode::NOERROR {
+            return Err(format!(
+                "response has rcode {}; validating negative answers \
+                 isn’t supported",
+                rcode
+            )
+            .into());
+        }
+
+        // If there is no data for the query type, check for a CNAME
+        // instead.
+        let (rrset, sigs) = Self::rrset(&msg, qname, self.qtype)?;
+        let (rtype, mut rrset, sigs) = if rrset.is_empty() {
+            let (rrset, sigs) = Self::rrset(&msg, qname, Rtype::CNAME)?;
+            (Rtype::CNAME, rrset, sigs)
+        } else {
+            (self.qtype, rrset, sigs)
+        };
+        if rrset.is_empty() {
+            return Err("no records in the answer; validating negative \
+                        answers isn’t supported"
+                .into());
+        }
+
+        println!(";; Validating {} {}", qname, rtype);
+
+        // Unsigned records still need to be checked to see whether they
+        // are below an insecure delegation.
+        let target = match sigs.first() {
+            Some(sig) => sig.signer_name().to_name(),
+            None => qname.clone(),
+        };
+        if !qname.ends_with(&target) {
+            return Err(Self::bogus(format!(
+                "signer {} is not an ancestor of {}",
+                target, qname
+            )));
+        }
+
+        let (zone, keys) = match self.secure_zone(client, &target).await {
+            Ok(ZoneState::Secure { zone, keys }) => (zone, keys),
+            Ok(ZoneState::Insecure { cut }) => {
+                println!(";; {} {}: insecure", qname, rtype);
+                return Ok((rrset, Some(cut)));
+            }
+            Ok(ZoneState::Unverified { cut }) => {
+                println!(
+                    "\n;; unverified: no proof that {} has no DS records",
+                    cut
+                );
+                return Err("validation incomplete".into());
+            }
+            Err(err) => return Err(Self::bogus(err)),
+        };
+        let key_tag = Self::verify_rrset(&mut rrset, &sigs, &keys, &zone)
+            .map_err(|err| {
+                Self::bogus(format!("{} {}: {}", qname, rtype, err))
+            })?;
+        println!(";; {} {}: secure (key id {})", qname, rtype, key_tag);
+
+        // A wildcard expansion is only secure if there is no closer match.
+        let labels = sigs
+            .iter()
+            .find(|sig| {
+                sig.key_tag() == key_tag && sig.signer_name().name_eq(&zone)
+            })
+            .map_or(u8::MAX, |sig| sig.labels());
+        match Self::wildcard_proof(&msg, qname, labels, &keys, &zone) {
+            Ok(None) => {}
+            Ok(Some(proof)) => println!("{}", proof),
+            Err(err) => {
+                println!(
+                    "\n;; not fully validated: {} {} is expanded from a \
+                     wildcard but {}",
+                    qname, rtype, err
+                );
+                return Err("validation incomplete".into());
+            }
+        }
+        Ok((rrset, None))
+    }
+}
+
+/// # Walking the chain of trust
+///
+impl Validate {
+    /// Follows the chain of trust from a trust anchor down to `target`.
+    ///
+    /// Returns the keys of the zone `target` if it is the apex of a signed
+    /// zone. If the chain ends at a delegation without DS records before
+    /// that, returns that delegation and whether the parent zone proved
+    /// the absence of the DS records. Any failure is returned as an error
+    /// describing why the chain is bogus.
+    async fn secure_zone(
+        &self,
+        client: &Client,
+        target: &Name<Vec<u8>>,
+    ) -> Result<ZoneState, String> {
+        let anchors = self.anchors_for(target)?;
+        let mut zone = anchors[0].owner.clone();
+        let dss = anchors
+            .iter()
+            .map(|anchor| anchor.ds.clone())
+            .collect::<Vec<_>>();
+        println!(";; {} is a trust anchor", zone);
+        let mut keys = Self::zone_keys(client, &zone, &dss).await?;
+
+        // Go through all names between the anchor and the target starting
+        // from the top.
+        let mut names = target
+            .iter_suffixes()
+            .take_while(|name| !name.name_eq(&zone))
+            .map(|name| name.to_name::<Vec<u8>>())
+            .collect::<Vec<_>>();
+        names.reverse();
+
+        for name in names {
+            let msg = Self::query(client, &name, Rtype::DS)
+                .await
+                .map_err(|err| format!("{} DS: {}", name, err))?;
+            let (mut ds_set, sigs) = Self::rrset(&msg, &name, Rtype::DS)
+                .map_err(|err| format!("{} DS: {}", name, err))?;
+            if ds_set.is_empty() {
+                // Without DS records, the name is either within the zone
+                // or an insecure delegation. Either way, the zone has to
+                // prove that there are no DS records.
+                let Some((record, sigs)) =
+                    Self::ds_denial(&msg, &name, &zone)
+                        .map_err(|err| format!("{} DS: {}", name, err))?
+                else {
+                    return Ok(ZoneState::Unverified { cut: name });
+                };
+                let (owner, rtype) =
+                    (record.owner().to_string(), record.rtype());
+                let delegation = Self::check_ds_denial(&record, &name)
+                    .map_err(|err| format!("{} {}: {}", owner, rtype, err))?;
+                let key_tag =
+                    Self::verify_rrset(&mut [record], &sigs, &keys, &zone)
+                        .map_err(|err| {
+                            format!("{} {}: {}", owner, rtype, err)
+                        })?;
+                if !delegation {
+                    println!(
+                        ";; {} {}: secure (key id {}), no delegation",
+                        owner, rtype, key_tag
+                    );
+                    continue;
+                }
+                println!(
+                    ";; {} {}: secure (key id {}), no DS records",
+                    owner, rtype, key_tag
+                );
+                return Ok(ZoneState::Insecure { cut: name });
+            }
+            let key_tag =
+                Self::verify_rrset(&mut ds_set, &sigs, &keys, &zone)
+                    .map_err(|err| format!("{} DS: {}", name, err))?;
+            println!(";; {} DS: secure (key id {})", name, key_tag);
+
+            let dss = ds_set
+                .iter()
+                .filter_map(|record| match record.data() {
+                    AllRecordData::Ds(ds) => Some(DsData {
+                        key_tag: ds.key_tag(),
+                        algorithm: ds.algorithm(),
+                        digest_type: ds.digest_type(),
+                        digest: ds.digest().to_vec(),
+                    }),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            keys = Self::zone_keys(client, &name, &dss).await?;
+            zone = name;
+        }
+        Ok(ZoneState::Secure { zone, keys })
+    }
+
+    /// Returns the trust anchors closest to the target.
+    fn anchors_for(
+        &self,
+        target: &Name<Vec<u8>>,
+    ) -> Result<Vec<TrustAnchor>, String> {
+        let anchors = if self.anchor.is_empty() {
+            ROOT_ANCHORS
+                .iter()
+                .map(|anchor| TrustAnchor::from_str(anchor))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            self.anchor.clone()
+        };
+        let Some(depth) = anchors
+            .iter()
+            .filter(|anchor| target.ends_with(&anchor.owner))
+            .map(|anchor| anchor.owner.label_count())
+            .max()
+        else {
+            return Err(format!("no trust anchor for {}", target));
+        };
+        Ok(anchors
+            .into_iter()
+            .filter(|anchor| {
+                target.ends_with(&anchor.owner)
+                    && anchor.owner.label_count() == depth
+            })
+            .collect())
+    }
+
+    /// Fetches and validates the keys of a zone.
+    ///
+    /// The DNSKEY RRset needs to be signed by a key that matches one of
+    /// the given DS records. Returns all keys of the zone.
+    async fn zone_keys(
+        client: &Client,
+        zone: &Name<Vec<u8>>,
+        dss: &[DsData],
+    ) -> Result<Vec<Dnskey<Bytes>>, String> {
+        let msg = Self::query(client, zone, Rtype::DNSKEY)
+            .await
+            .map_err(|err| format!("{} DNSKEY: {}", zone, err))?;
+        let (mut key_set, sigs) = Self::rrset(&msg, zone, Rtype::DNSKEY)
+            .map_err(|err| format!("{} DNSKEY: {}", zone, err))?;
+        let keys = key_set
+            .iter()
+            .filter_map(|record| match record.data() {
+                AllRecordData::Dnskey(key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let entry_keys = keys
+            .iter()
+            .filter(|key| dss.iter().any(|ds| ds.matches(zone, key)))
+            .cloned()
+            .collect::<Vec<_>>();
+        if entry_keys.is_empty() {
+            return Err(format!(
+                "{} DNSKEY: no key matches the DS records",
+                zone
+            ));
+        }
+        let key_tag =
+            Self::verify_rrset(&mut key_set, &sigs, &entry_keys, zone)
+                .map_err(|err| format!("{} DNSKEY: {}", zone, err))?;
+        println!(";; {} DNSKEY: secure (key id {})", zone, key_tag);
+        Ok(keys)
+    }
+
+    /// Returns the record denying the DS records of `name` with signatures.
+    ///
+    /// The record is an NSEC record owned by `name` or covering it as an
+    /// empty non-terminal, or the NSEC3 record matching the hash of `name`
+    /// in the authority section of the response to the DS query. Returns
+    /// `None` if there is no such record, for instance because the zone
+    /// uses NSEC3 opt-out.
+    fn ds_denial(
+        msg: &Message<Bytes>,
+        name: &Name<Vec<u8>>,
+        zone: &Name<Vec<u8>>,
+    ) -> Result<Option<(ParsedRecord, Vec<ParsedRrsig>)>, Error> {
+        let authority = Self::authority(msg)?;
+        let record = authority.iter().find(|record| match record.data() {
+            AllRecordData::Nsec(nsec) => {
+                record.owner().name_eq(name)
+                    || (Self::nsec_covers(record, nsec, name)
+                        && nsec.next_name().ends_with(name))
+            }
+            AllRecordData::Nsec3(nsec3) => Self::nsec3_hash(name, nsec3)
+                .is_some_and(|hash| Self::nsec3_owns(record, zone, &hash)),
+            _ => false,
+        });
+        Ok(record.map(|record| {
+            (record.clone(), Self::authority_sigs(&authority, record))
+        }))
+    }
+
+    /// Checks that an NSEC or NSEC3 record proves the absence of DS records.
+    ///
+    /// Returns whether `name` is a delegation, i.e., whether the record is
+    /// owned by `name` and its type bitmap contains NS but not SOA. An
+    /// NSEC record covering `name` proves an empty non-terminal.
+    fn check_ds_denial(
+        record: &ParsedRecord,
+        name: &Name<Vec<u8>>,
+    ) -> Result<bool, &'static str> {
+        let types = match record.data() {
+            AllRecordData::Nsec(_) if !record.owner().name_eq(name) => {
+                return Ok(false)
+            }
+            AllRecordData::Nsec(nsec) => nsec.types(),
+            AllRecordData::Nsec3(nsec3) => nsec3.types(),
+            _ => return Err("not a denial of existence"),
+        };
+        if types.contains(Rtype::DS) {
+            Err("type bitmap lists DS")
+        } else {
+            Ok(types.contains(Rtype::NS) && !types.contains(Rtype::SOA))
+        }
+    }
+
+    /// Checks that a wildcard expansion has no closer match.
+    ///
+    /// If the signature has fewer labels than `owner`, the RRset was
+    /// expanded from a wildcard. The authority section then has to contain
+    /// an NSEC record covering `owner` or an NSEC3 record covering the
+    /// hash of the next closer name, signed by a key of `zone`.
+    ///
+    /// Returns `None` if the RRset wasn’t expanded from a wildcard or a
+    /// description of the proof. Returns an error describing what is
+    /// missing if there is no valid proof.
+    fn wildcard_proof(
+        msg: &Message<Bytes>,
+        owner: &Name<Vec<u8>>,
+        labels: u8,
+        keys: &[Dnskey<Bytes>],
+        zone: &Name<Vec<u8>>,
+    ) -> Result<Option<String>, String> {
+        // The labels field counts neither the root nor a leading asterisk.
+        let labels = usize::from(labels);
+        let count = owner.label_count() - 1;
+        if labels >= count
+            || (labels + 1 == count && owner.first().as_slice() == b"*")
+        {
+            return Ok(None);
+        }
+        let next_closer = owner
+            .iter_suffixes()
+            .find(|name| name.label_count() == labels + 2)
+            .map(|name| name.to_name::<Vec<u8>>())
+            .ok_or("the signature has too many labels")?;
+
+        let authority =
+            Self::authority(msg).map_err(|err| err.to_string())?;
+        let mut err =
+            format!("there is no proof that {} doesn’t exist", owner);
+        for record in &authority {
+            let covers = match record.data() {
+                AllRecordData::Nsec(nsec) => {
+                    record.owner().ends_with(zone)
+                        && Self::nsec_covers(record, nsec, owner)
+                }
+                AllRecordData::Nsec3(nsec3) => {
+                    Self::nsec3_hash(&next_closer, nsec3).is_some_and(
+                        |hash| Self::nsec3_covers(record, zone, nsec3, &hash),
+                    )
+                }
+                _ => false,
+            };
+            if !covers {
+                continue;
+            }
+            let sigs = Self::authority_sigs(&authority, record);
+            let mut rrset = [record.clone()];
+            match Self::verify_rrset(&mut rrset, &sigs, keys, zone) {
+                Ok(key_tag) => {
+                    return Ok(Some(format!(
+                        ";; {} {}: secure (key id {}), no closer match",
+                        record.owner(),
+                        record.rtype(),
+                        key_tag
+                    )))
+                }
+                Err(reason) => {
+                    err = format!(
+                        "{} {}: {}",
+                        record.owner(),
+                        record.rtype(),
+                        reason
+                    );
+                }
+            }
+        }
+        Err(err)
+    }
+
+    /// Returns whether an NSEC record covers `name`.
+    ///
+    /// The name has to sort between the owner and the next name. The last
+    /// record of a zone points back to the apex and covers all names that
+    /// sort after its owner.
+    fn nsec_covers(
+        record: &ParsedRecord,
+        nsec: &Nsec<Bytes, ParsedName<Bytes>>,
+        name: &Name<Vec<u8>>,
+    ) -> bool {
+        let owner = record.owner();
+        let next = nsec.next_name();
+        Self::covers(
+            owner.name_cmp(name),
+            name.name_cmp(next),
+            owner.name_cmp(next),
+        )
+    }
+
+    /// Returns whether an NSEC3 record is the one for the given hash.
+    fn nsec3_owns(
+        record: &ParsedRecord,
+        zone: &Name<Vec<u8>>,
+        hash: &str,
+    ) -> bool {
+        Self::nsec3_owner_hash(record, zone)
+            .is_some_and(|owner| owner == hash)
+    }
+
+    /// Returns whether an NSEC3 record covers the given hash.
+    fn nsec3_covers(
+        record: &ParsedRecord,
+        zone: &Name<Vec<u8>>,
+        nsec3: &Nsec3<Bytes>,
+        hash: &str,
+    ) -> bool {
+        let Some(owner) = Self::nsec3_owner_hash(record, zone) else {
+            return false;
+        };
+        let next = nsec3.next_owner().to_string().to_ascii_uppercase();
+        Self::covers(
+            owner.as_str().cmp(hash),
+            hash.cmp(next.as_str()),
+            owner.cmp(&next),
+        )
+    }
+
+    /// Returns the hash in the owner of an NSEC3 record of `zone`.
+    ///
+    /// The hash is returned in upper case base 32 so hashes of the same
+    /// length sort like the binary hashes.
+    fn nsec3_owner_hash(
+        record: &ParsedRecord,
+        zone: &Name<Vec<u8>>,
+    ) -> Option<String> {
+        let owner = record.owner();
+        if owner.iter_labels().count() != zone.label_count() + 1
+            || !owner.ends_with(zone)
+        {
+            return None;
+        }
+        let label = owner.iter_labels().next()?;
+        Some(label.to_string().to_ascii_uppercase())
+    }
+
+    /// Returns whether a name lies between an owner and a next name.
+    ///
+    /// The arguments are the results of comparing the owner with the name,
+    /// the name with the next name, and the owner with the next name. If
+    /// the next name doesn’t sort after the owner, the chain wraps around.
+    fn covers(
+        owner_name: Ordering,
+        name_next: Ordering,
+        owner_next: Ordering,
+    ) -> bool {
+        if owner_next.is_lt() {
+            owner_name.is_lt() && name_next.is_lt()
+        } else {
+            owner_name.is_lt() || name_next.is_lt()
+        }
+    }
+
+    /// Returns the NSEC3 hash of `name` with the parameters of `nsec3`.
+    ///
+    /// The hash is returned in upper case base 32. Returns `None` if the
+    /// hash algorithm is unknown or the record asks for more than
+    /// [`MAX_NSEC3_ITERATIONS`].
+    fn nsec3_hash(
+        name: &Name<Vec<u8>>,
+        nsec3: &Nsec3<Bytes>,
+    ) -> Option<String> {
+        if nsec3.hash_algorithm() != Nsec3HashAlg::SHA1
+            || nsec3.iterations() > MAX_NSEC3_ITERATIONS
+        {
+            return None;
+        }
+        let hash = Self::nsec3_sha1(
+            name,
+            nsec3.salt().as_slice(),
+            nsec3.iterations(),
+        );
+        Some(hash.to_string().to_ascii_uppercase())
+    }
+
+    /// Calculates the SHA-1 NSEC3 hash of a name as defined in RFC 5155.
+    fn nsec3_sha1(
+        name: &Name<Vec<u8>>,
+        salt: &[u8],
+        iterations: u16,
+    ) -> OwnerHash<Vec<u8>> {
+        let sha1 = |data: &[u8]| {
+            digest::digest(
+                &digest::SHA1_FOR_LEGACY_USE_ONLY,
+                &[data, salt].concat(),
+            )
+        };
+        let mut hash = sha1(&name.as_slice().to_ascii_lowercase());
+        for _ in 0..iterations {
+            hash = sha1(hash.as_ref());
+        }
+        OwnerHash::from_octets(hash.as_ref().to_vec())
+            .expect("SHA-1 hash is short enough")
+    }
+
+    /// Verifies the RRset with one of the signatures and keys.
+    ///
+    /// Only signatures by `zone` that are currently valid are considered.
+    /// Returns the key tag of the key that verified the RRset.
+    fn verify_rrset(
+        rrset: &mut [ParsedRecord],
+        sigs: &[ParsedRrsig],
+        keys: &[Dnskey<Bytes>],
+        zone: &Name<Vec<u8>>,
+    ) -> Result<u16, String> {
+        if sigs.is_empty() {
+            return Err("no signatures".into());
+        }
+        let now = Timestamp::now().into_int();
+        let mut err = format!("no signature by a key of {}", zone);
+        for sig in sigs {
+            if !sig.signer_name().name_eq(zone) {
+                continue;
+            }
+            if Serial(now) < Serial(sig.inception().into_int()) {
+                err = format!(
+                    "signature by key {} not yet valid",
+                    sig.key_tag()
+                );
+                continue;
+            }
+            if Serial(now) > Serial(sig.expiration().into_int()) {
+                err = format!("signature by key {} expired", sig.key_tag());
+                continue;
+            }
+            let mut signed_data = Vec::new();
+            if sig.signed_data(&mut signed_data, rrset).is_err() {
+                return Err("cannot compose the signed data".into());
+            }
+            for key in keys.iter().filter(|key| {
+                key.algorithm() == sig.algorithm()
+                    && key.key_tag() == sig.key_tag()
+            }) {
+                match sig.verify_signed_data(key, &signed_data) {
+                    Ok(()) => return Ok(key.key_tag()),
+                    Err(e) => {
+                        err = format!(
+                            "signature by key {} does not verify: {}",
+                            sig.key_tag(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+        Err(err)
+    }
+}
+
+/// # Helpers
+///
+impl Validate {
+    /// Returns the client for sending queries.
+    fn client(&self) -> Client {
+        let mut client = match self.server {
+            Some(addr) => Client::with_servers(vec![Server {
+                addr: SocketAddr::new(addr, 53),
+                transport: Transport::UdpTcp,
+                timeout: Duration::from_secs_f32(self.timeout),
+                retries: 2,
+                udp_payload_size: 1232,
+                tls_hostname: None,
+                source: None,
+                proxy: None,
+                backoff: None,
+            }]),
+            None => Client::system(),
+        };
+        client.set_keep_connections(true);
+        client
+    }
+
+    /// Sends a query with the DO and CD flags set.
+    ///
+    /// With CD set, the server also returns data that fails its own
+    /// validation, so that we can find out why.
+    async fn query(
+        client: &Client,
+        qname: &Name<Vec<u8>>,
+        qtype: Rtype,
+    ) -> Result<Message<Bytes>, Error> {
+        let mut msg = MessageBuilder::new_vec();
+        msg.header_mut().set_rd(true);
+        msg.header_mut().set_cd(true);
+        msg.header_mut().set_random_id();
+        let mut msg = msg.question();
+        msg.push((qname, qtype)).unwrap();
+        let mut req = RequestMessage::new(msg);
+        req.set_dnssec_ok(true);
+        Ok(client.request(req).await?.message().clone())
+    }
+
+    /// Returns the RRset of the given type and its signatures.
+    fn rrset(
+        msg: &Message<Bytes>,
+        owner: &Name<Vec<u8>>,
+        rtype: Rtype,
+    ) -> Result<(Vec<ParsedRecord>, Vec<ParsedRrsig>), Error> {
+        let mut records = Vec::new();
+        let mut sigs = Vec::new();
+        for record in msg.answer()?.limit_to_in::<AllRecordData<_, _>>() {
+            let record = record?;
+            if !record.owner().name_eq(owner) {
+                continue;
+            }
+            match record.data() {
+                AllRecordData::Rrsig(sig) if sig.type_covered() == rtype => {
+                    sigs.push(sig.clone())
+                }
+                data if data.rtype() == rtype => records.push(record),
+                _ => {}
+            }
+        }
+        Ok((records, sigs))
+    }
+
+    /// Returns all records of the authority section.
+    fn authority(msg: &Message<Bytes>) -> Result<Vec<ParsedRecord>, Error> {
+        let mut res = Vec::new();
+        for record in msg.authority()?.limit_to_in::<AllRecordData<_, _>>() {
+            res.push(record?);
+        }
+        Ok(res)
+    }
+
+    /// Returns the signatures for a record from the authority section.
+    fn authority_sigs(
+        authority: &[ParsedRecord],
+        record: &ParsedRecord,
+    ) -> Vec<ParsedRrsig> {
+        authority
+            .iter()
+            .filter(|sig| sig.owner().name_eq(record.owner()))
+            .filter_map(|sig| match sig.data() {
+                AllRecordData::Rrsig(sig)
+                    if sig.type_covered() == record.rtype() =>
+                {
+                    Some(sig.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Prints the records of a validated RRset.
+    fn output_rrset(rrset: &[ParsedRecord]) {
+        for record in rrset {
+            println!(
+                "{} {} {} {} {}",
+                record.owner(),
+                record.ttl().as_secs(),
+                record.class(),
+                record.rtype(),
+                record.data()
+            );
+        }
+    }
+
+    /// Reports a bogus answer and returns the error for it.
+    fn bogus(reason: String) -> Error {
+        println!("\n;; bogus: {}", reason);
+        "validation failed".into()
+    }
+}
+
+//------------ ZoneState -----------------------------------------------------
+
+/// The outcome of following the chain of trust.
+enum ZoneState {
+    /// The zone is signed and its keys are trusted.
+    Secure {
+        zone: Name<Vec<u8>>,
+        keys: Vec<Dnskey<Bytes>>,
+    },
+
+    /// The chain of trust ends at a delegation without DS records.
+    Insecure { cut: Name<Vec<u8>> },
+
+    /// The chain ends at a name without DS records but their absence
+    /// could not be proven.
+    Unverified { cut: Name<Vec<u8>> },
+}
+
+//------------ DsData --------------------------------------------------------
+
+/// The data of a DS record.
+#[derive(Clone, Debug)]
+struct DsData {
+    key_tag: u16,
+    algorithm: SecAlg,
+    digest_type: DigestAlg,
+    digest: Vec<u8>,
+}
+
+impl DsData {
+    /// Returns whether the DS record refers to the given key of `zone`.
+    fn matches(&self, zone: &Name<Vec<u8>>, key: &Dnskey<Bytes>) -> bool {
+        key.key_tag() == self.key_tag
+            && key.algorithm() == self.algorithm
+            && key
+                .digest(zone, self.digest_type)
+                .is_ok_and(|digest| digest.as_ref() == self.digest.as_slice())
+    }
+}
+
+//------------ TrustAnchor ---------------------------------------------------
+
+/// A trust anchor given as a DS record.
+#[derive(Clone, Debug)]
+struct TrustAnchor {
+    owner: Name<Vec<u8>>,
+    ds: DsData,
+}
+
+impl FromStr for TrustAnchor {
+    type Err = String;
+
+    /// Parses a DS record in presentation format.
+    ///
+    /// The class is optional. The digest must not contain white space.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let mut next = |what: &str| {
+            fields
+                .next()
+                .ok_or_else(|| format!("missing {} in trust anchor", what))
+        };
+        let owner = Name::from_str(next("owner")?)
+            .map_err(|err| format!("invalid trust anchor owner: {}", err))?;
+        let mut rtype = next("record type")?;
+        if rtype.eq_ignore_ascii_case("IN") {
+            rtype = next("record type")?;
+        }
+        if !rtype.eq_ignore_ascii_case("DS") {
+            return Err("trust anchor must be a DS record".into());
+        }
+        let key_tag = u16::from_str(next("key tag")?)
+            .map_err(|_| "invalid trust anchor key tag")?;
+        let algorithm = u8::from_str(next("algorithm")?)
+            .map_err(|_| "invalid trust anchor algorithm")?;
+        let digest_type = u8::from_str(next("digest type")?)
+            .map_err(|_| "invalid trust anchor digest type")?;
+        let digest = HexData::from_str(next("digest")?)
+            .map_err(|err| format!("invalid trust anchor digest: {}", err))?;
+        if fields.next().is_some() {
+            return Err("trailing data in trust anchor".into());
+        }
+        Ok(TrustAnchor {
+            owner,
+            ds: DsData {
+                key_tag,
+                algorithm: SecAlg::from_int(algorithm),
+                digest_type: DigestAlg::from_int(digest_type),
+                digest: digest.0,
+            },
+        })
+    }
+}
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use domain::base::iana::Class;
+    use domain::base::Ttl;
+    use domain::rdata::dnssec::RtypeBitmap;
+    use domain::rdata::A;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    /// A record for building a test response.
+    type TestRecord = Record<Name<Bytes>, AllRecordData<Bytes, Name<Bytes>>>;
+
+    fn name(s: &str) -> Name<Vec<u8>> {
+        Name::from_str(s).unwrap()
+    }
+
+    fn record(
+        owner: &str,
+        data: AllRecordData<Bytes, Name<Bytes>>,
+    ) -> TestRecord {
+        Record::new(
+            Name::from_str(owner).unwrap(),
+            Class::IN,
+            Ttl::from_secs(3600),
+            data,
+        )
+    }
+
+    fn a(owner: &str, addr: [u8; 4]) -> TestRecord {
+        record(owner, AllRecordData::A(A::new(addr.into())))
+    }
+
+    fn nsec(owner: &str, next: &str, rtypes: &[Rtype]) -> TestRecord {
+        let mut types = RtypeBitmap::<Bytes>::builder();
+        for rtype in rtypes {
+            types.add(*rtype).unwrap();
+        }
+        record(
+            owner,
+            AllRecordData::Nsec(Nsec::new(
+                Name::from_str(next).unwrap(),
+                types.finalize(),
+            )),
+        )
+    }
+
+    /// Returns a response with the given answer and authority sections.
+    fn response(
+        answer: &[TestRecord],
+        authority: &[TestRecord],
+    ) -> Message<Bytes> {
+        let mut msg = MessageBuilder::new_vec().answer();
+        for record in answer {
+            msg.push(record.clone()).unwrap();
+        }
+        let mut msg = msg.authority();
+        for record in authority {
+            msg.push(record.clone()).unwrap();
+        }
+        Message::from_octets(Bytes::from(msg.finish())).unwrap()
+    }
+
+    //--- Zone
+
+    /// A zone with an Ed25519 key for signing fixtures.
+    struct Zone {
+        apex: Name<Vec<u8>>,
+        pair: Ed25519KeyPair,
+        key: Dnskey<Bytes>,
+    }
+
+    impl Zone {
+        fn new(apex: &str) -> Self {
+            let pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+            let key = Dnskey::new(
+                257,
+                3,
+                SecAlg::ED25519,
+                Bytes::copy_from_slice(pair.public_key().as_ref()),
+            )
+            .unwrap();
+            Zone {
+                apex: name(apex),
+                pair,
+                key,
+            }
+        }
+
+        /// Returns the RRSIG record for an RRset valid for an hour.
+        fn sign(&self, rrset: &[TestRecord], labels: u8) -> TestRecord {
+            let first = &rrset[0];
+            let now = Timestamp::now().into_int();
+            let rrsig = |signature| {
+                Rrsig::new(
+                    first.rtype(),
+                    SecAlg::ED25519,
+                    labels,
+                    first.ttl(),
+                    Timestamp::from(now.wrapping_add(3600)),
+                    Timestamp::from(now.wrapping_sub(3600)),
+                    self.key.key_tag(),
+                    Name::from_octets(Bytes::from(
+                        self.apex.as_slice().to_vec(),
+                    ))
+                    .unwrap(),
+                    signature,
+                )
+                .unwrap()
+            };
+            let mut data = Vec::new();
+            rrsig(Bytes::new())
+                .signed_data(&mut data, &mut rrset.to_vec())
+                .unwrap();
+            let signature = self.pair.sign(&data);
+            Record::new(
+                first.owner().clone(),
+                Class::IN,
+                first.ttl(),
+                AllRecordData::Rrsig(rrsig(Bytes::copy_from_slice(
+                    signature.as_ref(),
+                ))),
+            )
+        }
+
+        fn verify(
+            &self,
+            msg: &Message<Bytes>,
+            owner: &str,
+            rtype: Rtype,
+        ) -> Result<u16, String> {
+            let (mut rrset, sigs) =
+                Validate::rrset(msg, &name(owner), rtype).unwrap();
+            Validate::verify_rrset(
+                &mut rrset,
+                &sigs,
+                &[self.key.clone()],
+                &self.apex,
+            )
+        }
+
+        fn wildcard_proof(
+            &self,
+            msg: &Message<Bytes>,
+            owner: &str,
+            labels: u8,
+        ) -> Result<Option<String>, String> {
+            Validate::wildcard_proof(
+                msg,
+                &name(owner),
+                labels,
+                &[self.key.clone()],
+                &self.apex,
+            )
+        }
+    }
+
+    //--- Tests
+
+    #[test]
+    fn signed_rrset() {
+        let zone = Zone::new("example.");
+        let rrset = [
+            a("www.example.", [192, 0, 2, 1]),
+            a("www.example.", [192, 0, 2, 2]),
+        ];
+        let sig = zone.sign(&rrset, 2);
+        let msg =
+            response(&[rrset[0].clone(), rrset[1].clone(), sig.clone()], &[]);
+        assert_eq!(
+            zone.verify(&msg, "www.example.", Rtype::A),
+            Ok(zone.key.key_tag())
+        );
+
+        // Changed data doesn’t verify.
+        let msg = response(&[rrset[0].clone(), sig.clone()], &[]);
+        assert!(zone.verify(&msg, "www.example.", Rtype::A).is_err());
+
+        // Neither do records without signatures or a different zone.
+        let msg = response(&rrset, &[]);
+        assert!(zone.verify(&msg, "www.example.", Rtype::A).is_err());
+        let other = Zone::new("example.org.");
+        let msg = response(&[rrset[0].clone(), rrset[1].clone(), sig], &[]);
+        assert!(other.verify(&msg, "www.example.", Rtype::A).is_err());
+    }
+
+    #[test]
+    fn wildcard_proof() {
+        let zone = Zone::new("example.");
+        let rrset = [a("a.example.", [192, 0, 2, 1])];
+
+        // Records that aren’t expanded from a wildcard need no proof.
+        let msg = response(&rrset, &[]);
+        assert_eq!(zone.wildcard_proof(&msg, "a.example.", 2), Ok(None));
+        assert_eq!(zone.wildcard_proof(&msg, "*.example.", 1), Ok(None));
+
+        // An expanded record needs a signed NSEC covering the name.
+        assert!(zone.wildcard_proof(&msg, "a.example.", 1).is_err());
+        let covering = [nsec("example.", "b.example.", &[Rtype::SOA])];
+        let msg = response(&rrset, &covering);
+        assert!(zone.wildcard_proof(&msg, "a.example.", 1).is_err());
+        let sig = zone.sign(&covering, 1);
+        let msg = response(&rrset, &[covering[0].clone(), sig]);
+        assert!(zone
+            .wildcard_proof(&msg, "a.example.", 1)
+            .is_ok_and(|proof| proof.is_some()));
+
+        // An NSEC that doesn’t cover the name proves nothing.
+        let other = [nsec("b.example.", "c.example.", &[Rtype::A])];
+        let sig = zone.sign(&other, 2);
+        let msg = response(&rrset, &[other[0].clone(), sig]);
+        assert!(zone.wildcard_proof(&msg, "a.example.", 1).is_err());
+    }
+
+    #[test]
+    fn ds_denial() {
+        let zone = name("example.");
+        let denial = |authority: &[TestRecord], owner: &str| {
+            let msg = response(&[], authority);
+            let name = name(owner);
+            Validate::ds_denial(&msg, &name, &zone)
+                .ok()
+                .flatten()
+                .map(|(record, _)| Validate::check_ds_denial(&record, &name))
+        };
+
+        // An insecure delegation.
+        let types = [Rtype::NS, Rtype::RRSIG, Rtype::NSEC];
+        let records = [nsec("sub.example.", "x.example.", &types)];
+        assert_eq!(denial(&records, "sub.example."), Some(Ok(true)));
+
+        // A name within the zone and an empty non-terminal.
+        let records = [nsec("sub.example.", "x.example.", &[Rtype::A])];
+        assert_eq!(denial(&records, "sub.example."), Some(Ok(false)));
+        let records = [nsec("a.example.", "c.b.example.", &[Rtype::A])];
+        assert_eq!(denial(&records, "b.example."), Some(Ok(false)));
+
+        // DS records must not exist and there must be a denial at all.
+        let types = [Rtype::NS, Rtype::DS];
+        let records = [nsec("sub.example.", "x.example.", &types)];
+        assert!(
+            denial(&records, "sub.example.").is_some_and(|res| res.is_err())
+        );
+        let records = [nsec("a.example.", "c.example.", &[Rtype::A])];
+        assert_eq!(denial(&records, "sub.example."), None);
+    }
+
+    #[test]
+    fn covers() {
+        use Ordering::{Greater, Less};
+
+        assert!(Validate::covers(Less, Less, Less));
+        assert!(!Validate::covers(Less, Greater, Less));
+        assert!(!Validate::covers(Greater, Less, Less));
+
+        // The last record of the chain wraps around to the first.
+        assert!(Validate::covers(Less, Greater, Greater));
+        assert!(Validate::covers(Greater, Less, Greater));
+        assert!(!Validate::covers(Greater, Greater, Greater));
+    }
+
+    #[test]
+    fn nsec3_hash() {
+        // The examples from appendix A of RFC 5155.
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        let hash = |owner| {
+            Validate::nsec3_sha1(&name(owner), &salt, 12)
+                .to_string()
+                .to_ascii_lowercase()
+        };
+        assert_eq!(hash("example."), "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom");
+        assert_eq!(hash("a.example."), "35mthgpgcu1qg68fab165klnsnk3dpvl");
+    }
+}
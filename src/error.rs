@@ -1,55 +1,173 @@
 //! Error handling.
 
+use domain::base::iana::Rcode;
 use domain::base::wire::ParseError;
 use domain::net::client::request;
 use std::borrow::Cow;
+use std::sync::Arc;
 use std::{error, fmt, io};
 
 //------------ Error ---------------------------------------------------------
 
+/// An error happened while executing a command.
+///
+/// The variants allow telling apart the various kinds of failure, most
+/// importantly those caused by the network from those caused by the
+/// response.
 #[derive(Clone, Debug)]
-pub struct Error {
-    message: Cow<'static, str>,
+pub enum Error {
+    /// An I/O error happened.
+    Io(Arc<io::Error>),
+
+    /// Writing the output failed.
+    ///
+    /// This is kept apart from [`Error::Io`] so that, e.g., a broken pipe
+    /// on stdout isn’t reported as a network failure.
+    Output(Arc<io::Error>),
+
+    /// A message could not be parsed.
+    Parse(ParseError),
+
+    /// Sending a request or receiving its response failed.
+    Request(request::Error),
+
+    /// No response was received in time.
+    Timeout(Cow<'static, str>),
+
+    /// A transport failed for a reason other than I/O.
+    Transport(Cow<'static, str>),
+
+    /// The configuration or command line is not usable.
+    Config(Cow<'static, str>),
+
+    /// The server responded with an error rcode.
+    Rcode(Rcode),
+
+    /// Any other error.
+    Other(Cow<'static, str>),
+}
+
+impl Error {
+    /// The exit code for errors not covered by any other code.
+    pub const EXIT_FAILURE: u8 = 1;
+
+    /// The exit code if the server answered with NXDOMAIN.
+    pub const EXIT_NXDOMAIN: u8 = 3;
+
+    /// The exit code if the server answered with any other error rcode.
+    pub const EXIT_SERVER_FAILURE: u8 = 4;
+
+    /// The exit code for network and I/O errors, including timeouts.
+    pub const EXIT_TRANSPORT: u8 = 5;
+
+    /// Creates a timeout error with the given message.
+    pub fn timeout(message: impl Into<Cow<'static, str>>) -> Self {
+        Self::Timeout(message.into())
+    }
+
+    /// Creates a transport error from anything displayable.
+    pub fn transport(err: impl fmt::Display) -> Self {
+        Self::Transport(err.to_string().into())
+    }
+
+    /// Creates an error for failing to write the output.
+    pub fn output(err: io::Error) -> Self {
+        Self::Output(Arc::new(err))
+    }
+
+    /// Creates a configuration error with the given message.
+    pub fn config(message: impl Into<Cow<'static, str>>) -> Self {
+        Self::Config(message.into())
+    }
+
+    /// Returns the process exit code that should be used for the error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Io(_)
+            | Self::Request(_)
+            | Self::Timeout(_)
+            | Self::Transport(_) => Self::EXIT_TRANSPORT,
+            Self::Rcode(Rcode::NXDOMAIN) => Self::EXIT_NXDOMAIN,
+            Self::Rcode(_) => Self::EXIT_SERVER_FAILURE,
+            Self::Output(_)
+            | Self::Parse(_)
+            | Self::Config(_)
+            | Self::Other(_) => Self::EXIT_FAILURE,
+        }
+    }
 }
 
 impl From<&'static str> for Error {
     fn from(message: &'static str) -> Self {
-        Self {
-            message: Cow::Borrowed(message),
-        }
+        Self::Other(Cow::Borrowed(message))
     }
 }
 
 impl From<String> for Error {
     fn from(message: String) -> Self {
-        Self {
-            message: Cow::Owned(message),
-        }
+        Self::Other(Cow::Owned(message))
     }
 }
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Self::from(err.to_string())
+        Self::Io(Arc::new(err))
     }
 }
 
 impl From<ParseError> for Error {
-    fn from(_err: ParseError) -> Self {
-        Self::from("message parse error")
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
     }
 }
 
 impl From<request::Error> for Error {
     fn from(err: request::Error) -> Self {
-        Self::from(err.to_string())
+        Self::Request(err)
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.message, f)
+        match self {
+            Self::Io(err) | Self::Output(err) => fmt::Display::fmt(err, f),
+            Self::Parse(_) => f.write_str("message parse error"),
+            Self::Request(err) => fmt::Display::fmt(err, f),
+            Self::Rcode(rcode) => {
+                write!(f, "server responded with {}", rcode)
+            }
+            Self::Timeout(message)
+            | Self::Transport(message)
+            | Self::Config(message)
+            | Self::Other(message) => fmt::Display::fmt(message, f),
+        }
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) | Self::Output(err) => Some(err.as_ref()),
+            Self::Request(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let pipe = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert_eq!(Error::from(pipe()).exit_code(), Error::EXIT_TRANSPORT);
+        assert_eq!(Error::output(pipe()).exit_code(), Error::EXIT_FAILURE);
+        assert_eq!(
+            Error::Rcode(Rcode::NXDOMAIN).exit_code(),
+            Error::EXIT_NXDOMAIN
+        );
+    }
+}
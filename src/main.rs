@@ -1,9 +1,11 @@
 //! The _dnsi_ binary.
 
-use clap::Parser;
+use std::process::ExitCode;
 
-fn main() {
-    if let Err(err) = dnsi::Args::parse().execute() {
+fn main() -> ExitCode {
+    if let Err(err) = dnsi::Args::from_env().execute() {
         eprintln!("{}", err);
+        return ExitCode::from(err.exit_code());
     }
+    ExitCode::SUCCESS
 }
@@ -1,6 +1,35 @@
 //! Common ANSI codes
 
-pub static BOLD: &str = "\x1B[1m";
-pub static UNDERLINE: &str = "\x1B[4m";
-pub static ITALIC: &str = "\x1B[3m";
-pub static RESET: &str = "\x1B[m";
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub static BOLD: Code = Code("\x1B[1m");
+pub static UNDERLINE: Code = Code("\x1B[4m");
+pub static ITALIC: Code = Code("\x1B[3m");
+pub static RED: Code = Code("\x1B[31m");
+pub static YELLOW: Code = Code("\x1B[33m");
+pub static RESET: Code = Code("\x1B[m");
+
+/// Whether the codes are written at all.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether the codes are written or replaced by empty strings.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+//------------ Code ----------------------------------------------------------
+
+/// An ANSI code that is only displayed if codes are enabled.
+#[derive(Clone, Copy, Debug)]
+pub struct Code(&'static str);
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if ENABLED.load(Ordering::Relaxed) {
+            f.write_str(self.0)
+        } else {
+            Ok(())
+        }
+    }
+}
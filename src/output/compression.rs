@@ -0,0 +1,152 @@
+//! Statistics on the use of name compression in a message.
+
+use std::fmt;
+
+//------------ CompressionStats ----------------------------------------------
+
+/// How much of a message made use of name compression.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompressionStats {
+    /// The number of domain names in the message that were looked at.
+    pub names: usize,
+
+    /// The number of compression pointers encountered.
+    pub pointers: usize,
+
+    /// The number of bytes saved by compression.
+    pub saved: usize,
+
+    /// The size of the message as received.
+    pub size: usize,
+}
+
+impl CompressionStats {
+    /// Collects the statistics for the given wire-format message.
+    ///
+    /// This considers owner names, the names in the question, and the
+    /// names in the record data of those record types that may use
+    /// compression according to RFC 3597.
+    ///
+    /// Returns `None` if the message is malformed.
+    pub fn from_message(msg: &[u8]) -> Option<Self> {
+        let mut res = CompressionStats {
+            size: msg.len(),
+            ..Default::default()
+        };
+
+        let count = |pos: usize| {
+            msg.get(pos..pos + 2)
+                .map(|s| usize::from(u16::from_be_bytes([s[0], s[1]])))
+        };
+        let qdcount = count(4)?;
+        let rrcount = count(6)? + count(8)? + count(10)?;
+
+        let mut pos = 12;
+        for _ in 0..qdcount {
+            pos = res.name(msg, pos)?;
+            pos += 4;
+        }
+        for _ in 0..rrcount {
+            pos = res.name(msg, pos)?;
+            let rtype = count(pos)?;
+            let rdlen = count(pos + 8)?;
+            pos += 10;
+            let end = pos + rdlen;
+            if end > msg.len() {
+                return None;
+            }
+            match rtype {
+                // NS, MD, MF, CNAME, MB, MG, MR, PTR
+                2 | 3 | 4 | 5 | 7 | 8 | 9 | 12 => {
+                    res.name(msg, pos)?;
+                }
+                // SOA, MINFO
+                6 | 14 => {
+                    let next = res.name(msg, pos)?;
+                    res.name(msg, next)?;
+                }
+                // MX
+                15 => {
+                    res.name(msg, pos + 2)?;
+                }
+                _ => {}
+            }
+            pos = end;
+        }
+        Some(res)
+    }
+
+    /// Returns the size of the message had no compression been used.
+    pub fn uncompressed_size(&self) -> usize {
+        self.size + self.saved
+    }
+
+    /// Processes the name starting at `pos`.
+    ///
+    /// Returns the position of the first octet after the name.
+    fn name(&mut self, msg: &[u8], mut pos: usize) -> Option<usize> {
+        self.names += 1;
+        loop {
+            let len = *msg.get(pos)?;
+            match len & 0xC0 {
+                0x00 if len == 0 => return Some(pos + 1),
+                0x00 => pos += usize::from(len) + 1,
+                0xC0 => {
+                    let target = usize::from(u16::from_be_bytes([
+                        len & 0x3F,
+                        *msg.get(pos + 1)?,
+                    ]));
+                    self.pointers += 1;
+                    self.saved +=
+                        Self::suffix_len(msg, target)?.saturating_sub(2);
+                    return Some(pos + 2);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the uncompressed length of the name starting at `pos`.
+    fn suffix_len(msg: &[u8], mut pos: usize) -> Option<usize> {
+        let mut res = 0;
+        // Every pointer has to point backwards, so we can’t see more
+        // pointers than there are octets in the message.
+        for _ in 0..msg.len() {
+            let len = *msg.get(pos)?;
+            match len & 0xC0 {
+                0x00 if len == 0 => return Some(res + 1),
+                0x00 => {
+                    res += usize::from(len) + 1;
+                    pos += usize::from(len) + 1;
+                }
+                0xC0 => {
+                    let target = usize::from(u16::from_be_bytes([
+                        len & 0x3F,
+                        *msg.get(pos + 1)?,
+                    ]));
+                    if target >= pos {
+                        return None;
+                    }
+                    pos = target;
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for CompressionStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} names, {} compression pointers, {} bytes saved \
+             ({} bytes received, {} bytes uncompressed)",
+            self.names,
+            self.pointers,
+            self.saved,
+            self.size,
+            self.uncompressed_size()
+        )
+    }
+}
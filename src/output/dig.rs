@@ -3,19 +3,82 @@
 use crate::client::Answer;
 use domain::base::iana::Rtype;
 use domain::base::opt::AllOptData;
-use domain::base::ParsedRecord;
+use domain::base::rdata::UnknownRecordData;
+use domain::base::{Message, ParsedRecord};
 use domain::rdata::AllRecordData;
 use std::io;
 
 use super::error::OutputError;
+use super::{format_offset, AdditionalCounts, OutputOptions};
 
 //------------ write ---------------------------------------------------------
 
 pub fn write(
     answer: &Answer,
+    options: &OutputOptions,
     target: &mut impl io::Write,
 ) -> Result<(), OutputError> {
     let msg = answer.msg_slice();
+    write_message(msg, options, target)?;
+    if options.answer_only {
+        return Ok(());
+    }
+
+    // Stats
+    let stats = answer.stats();
+    writeln!(
+        target,
+        "\n;; Query time: {} msec",
+        stats.duration.num_milliseconds()
+    )?;
+    writeln!(
+        target,
+        ";; SERVER: {}#{} ({})",
+        super::server_ip(answer),
+        stats.server_addr.port(),
+        stats.server_proto
+    )?;
+    if let Some(attempts) = stats.attempts {
+        writeln!(target, ";; ATTEMPTS: {}", attempts)?;
+    }
+    if let Some(tls) = answer.tls() {
+        writeln!(
+            target,
+            ";; TLS: {}, cipher suite: {}, ALPN: {}, server name: {}",
+            tls.version.unwrap_or("unknown"),
+            tls.cipher_suite.as_deref().unwrap_or("unknown"),
+            tls.alpn.as_ref().map_or_else(
+                || "none".into(),
+                |alpn| String::from_utf8_lossy(alpn)
+            ),
+            tls.server_name
+        )?;
+    }
+    writeln!(
+        target,
+        ";; WHEN: {}",
+        stats.start.format("%a %b %d %H:%M:%S %Z %Y")
+    )?;
+    writeln!(target, ";; MSG SIZE  rcvd: {}", msg.as_slice().len())?;
+
+    Ok(())
+}
+
+//------------ write_message -------------------------------------------------
+
+/// Writes a message without any information on how it was received.
+pub fn write_message(
+    msg: Message<&[u8]>,
+    options: &OutputOptions,
+    target: &mut impl io::Write,
+) -> Result<(), OutputError> {
+    if options.answer_only {
+        let offsets = options.record_offsets(msg.as_slice());
+        for (item, offset) in msg.answer()?.zip(offsets) {
+            write_record_item(target, options, &item?, offset)?;
+        }
+        return Ok(());
+    }
 
     // Header
     let header = msg.header();
@@ -38,9 +101,7 @@ pub fn write(
         counts.arcount()
     )?;
 
-    let opt = msg.opt(); // We need it further down ...
-
-    if let Some(opt) = opt.as_ref() {
+    if let Some(opt) = msg.opt() {
         writeln!(target, "\n;; OPT PSEUDOSECTION:")?;
         writeln!(
             target,
@@ -92,6 +153,34 @@ pub fn write(
         }
     }
 
+    // Sections
+    //
+    // A malformed record means we can’t find the records after it, but we
+    // still want to show everything before it as well as the stats.
+    match write_sections(target, options, &msg) {
+        Ok(()) => {}
+        Err(OutputError::BadRecord(err)) => {
+            writeln!(
+                target,
+                "\n;; ERROR: malformed message, remaining records \
+                 skipped: {}",
+                err
+            )?;
+        }
+        Err(err) => return Err(err),
+    }
+
+    Ok(())
+}
+
+fn write_sections(
+    target: &mut impl io::Write,
+    options: &OutputOptions,
+    msg: &Message<&[u8]>,
+) -> Result<(), OutputError> {
+    let counts = msg.header_counts();
+    let mut offsets = options.record_offsets(msg.as_slice());
+
     // Question
     let questions = msg.question();
     if counts.qdcount() > 0 {
@@ -106,8 +195,8 @@ pub fn write(
     let section = questions.answer()?;
     if counts.ancount() > 0 {
         writeln!(target, "\n;; ANSWER SECTION:")?;
-        for item in section {
-            write_record_item(target, &item?)?;
+        for (item, offset) in section.zip(&mut offsets) {
+            write_record_item(target, options, &item?, offset)?;
         }
     }
 
@@ -115,50 +204,39 @@ pub fn write(
     let section = section.next_section()?.unwrap();
     if counts.nscount() > 0 {
         writeln!(target, "\n;; AUTHORITY SECTION:")?;
-        for item in section {
-            write_record_item(target, &item?)?;
+        for (item, offset) in section.zip(&mut offsets) {
+            write_record_item(target, options, &item?, offset)?;
         }
     }
 
     // Additional
     let section = section.next_section()?.unwrap();
-    if counts.arcount() > 1 || (opt.is_none() && counts.arcount() > 0) {
+    let additional = AdditionalCounts::new(section)?;
+    if additional.opt > 1 {
+        writeln!(
+            target,
+            "\n;; WARNING: {} OPT records in the additional section.",
+            additional.opt
+        )?;
+    }
+    if additional.other > 0 {
         writeln!(target, "\n;; ADDITIONAL SECTION:")?;
-        for item in section {
+        for (item, offset) in section.zip(offsets) {
             let item = item?;
             if item.rtype() != Rtype::OPT {
-                write_record_item(target, &item)?;
+                write_record_item(target, options, &item, offset)?;
             }
         }
     }
 
-    // Stats
-    let stats = answer.stats();
-    writeln!(
-        target,
-        "\n;; Query time: {} msec",
-        stats.duration.num_milliseconds()
-    )?;
-    writeln!(
-        target,
-        ";; SERVER: {}#{} ({})",
-        stats.server_addr.ip(),
-        stats.server_addr.port(),
-        stats.server_proto
-    )?;
-    writeln!(
-        target,
-        ";; WHEN: {}",
-        stats.start.format("%a %b %d %H:%M:%S %Z %Y")
-    )?;
-    writeln!(target, ";; MSG SIZE  rcvd: {}", msg.as_slice().len())?;
-
     Ok(())
 }
 
 fn write_record_item(
     target: &mut impl io::Write,
+    options: &OutputOptions,
     item: &ParsedRecord<&[u8]>,
+    offset: Option<usize>,
 ) -> Result<(), io::Error> {
     let parsed = item.to_any_record::<AllRecordData<_, _>>();
 
@@ -166,12 +244,20 @@ fn write_record_item(
         write!(target, "; ")?;
     }
 
-    let data = match parsed {
-        Ok(item) => item.data().to_string(),
-        Err(_) => "<invalid data>".into(),
+    // Show data we don’t understand in the generic format of RFC 3597.
+    let multiline = options.split_record(item);
+    let data = match (&multiline, parsed) {
+        (Some(multiline), _) => multiline.first(),
+        (None, Ok(item)) => item.data().to_string(),
+        (None, Err(_)) => {
+            match item.to_any_record::<UnknownRecordData<_>>() {
+                Ok(item) => item.data().to_string(),
+                Err(_) => "<invalid data>".into(),
+            }
+        }
     };
 
-    writeln!(
+    write!(
         target,
         "{}  {}  {}  {}  {}",
         item.owner(),
@@ -179,5 +265,82 @@ fn write_record_item(
         item.class(),
         item.rtype(),
         data
-    )
+    )?;
+    let comments = offset
+        .map(|offset| format!("offset {}", format_offset(offset)))
+        .into_iter()
+        .chain(options.record_note(item))
+        .collect::<Vec<_>>();
+    if !comments.is_empty() {
+        write!(target, "  ; {}", comments.join(", "))?;
+    }
+    writeln!(target)?;
+    if let Some(multiline) = multiline {
+        for line in multiline.tail("\t\t\t\t") {
+            writeln!(target, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+    use domain::base::iana::Class;
+    use domain::base::{MessageBuilder, Name, Record, Ttl};
+    use domain::rdata::A;
+    use std::str::FromStr;
+
+    /// Creates a response with a corrupt A record and then a good one.
+    fn response() -> Vec<u8> {
+        let name = Name::<Vec<u8>>::from_str("example.com").unwrap();
+        let ttl = Ttl::from_secs(3600);
+
+        let mut msg = MessageBuilder::new_vec();
+        msg.header_mut().set_qr(true);
+        let mut msg = msg.question();
+        msg.push((&name, Rtype::A)).unwrap();
+        let mut msg = msg.answer();
+        let bad = UnknownRecordData::from_octets(Rtype::A, vec![192, 0, 2])
+            .unwrap();
+        msg.push(Record::new(&name, Class::IN, ttl, bad)).unwrap();
+        let good = A::new([192, 0, 2, 1].into());
+        msg.push(Record::new(&name, Class::IN, ttl, good)).unwrap();
+        msg.finish()
+    }
+
+    /// Returns the output of the message.
+    fn output(msg: &[u8]) -> String {
+        let mut target = Vec::new();
+        assert!(write_message(
+            Message::from_octets(msg).unwrap(),
+            &OutputOptions::parse_from(["dnsi"]),
+            &mut target,
+        )
+        .is_ok());
+        String::from_utf8(target).unwrap()
+    }
+
+    #[test]
+    fn corrupt_rdata() {
+        let output = output(&response());
+        assert!(output
+            .lines()
+            .any(|line| line.starts_with("; ") && line.contains("\\# 3 ")));
+        assert!(output.lines().any(|line| line.ends_with("192.0.2.1")));
+        assert!(!output.contains("ERROR"));
+    }
+
+    #[test]
+    fn truncated_record() {
+        let mut msg = response();
+        msg.truncate(msg.len() - 2);
+        let output = output(&msg);
+        assert!(output.lines().any(|line| line.contains("\\# 3 ")));
+        assert!(!output.contains("192.0.2.1"));
+        assert!(output.contains(";; ERROR: malformed message"));
+    }
 }
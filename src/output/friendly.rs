@@ -1,36 +1,62 @@
 //! An output format designed to be read by humans.
 
+use chrono::{DateTime, Local};
 use domain::base::iana::Rtype;
 use domain::base::opt::{AllOptData, OptRecord};
+use domain::base::rdata::UnknownRecordData;
 use domain::base::wire::ParseError;
 use domain::base::{
     Header, HeaderCounts, Message, ParsedRecord, QuestionSection,
 };
 use domain::rdata::AllRecordData;
+use domain::utils::base64;
 use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 use super::ansi::{BOLD, RESET};
 use super::error::OutputError;
-use super::ttl;
+use super::{format_offset, AdditionalCounts, OutputOptions};
 use crate::client::Answer;
 
 use super::table_writer::TableWriter;
 
 pub fn write(
     answer: &Answer,
+    options: &OutputOptions,
     target: &mut impl io::Write,
 ) -> Result<(), OutputError> {
     let msg = answer.msg_slice();
+    write_message(msg, answer.stats().start, options, target)?;
+    if !options.answer_only {
+        write_stats(target, msg, answer)?;
+    }
+    Ok(())
+}
+
+/// Writes a message without any information on how it was received.
+pub fn write_message(
+    msg: Message<&[u8]>,
+    start: DateTime<Local>,
+    options: &OutputOptions,
+    target: &mut impl io::Write,
+) -> Result<(), OutputError> {
+    let mut offsets = options.record_offsets(msg.as_slice());
+
+    if options.answer_only {
+        return write_answer_table(
+            target,
+            options,
+            start,
+            msg.answer()?.zip(offsets),
+        );
+    }
 
     let header = msg.header();
     let counts = msg.header_counts();
 
     write_header(target, header, counts)?;
 
-    // We need opt further down
-    let opt = msg.opt();
-
-    if let Some(opt) = &opt {
+    if let Some(opt) = &msg.opt() {
         write_opt(target, opt)?;
     }
 
@@ -42,28 +68,46 @@ pub fn write(
     let section = questions.answer()?;
     if counts.ancount() > 0 {
         writeln!(target, "\n{BOLD}ANSWER SECTION{RESET}")?;
-        write_answer_table(target, section)?;
+        write_answer_table(
+            target,
+            options,
+            start,
+            section.zip(&mut offsets),
+        )?;
     }
 
     let mut section = section.next_section()?.unwrap();
     if counts.nscount() > 0 {
         writeln!(target, "\n{BOLD}AUTHORITY SECTION{RESET}")?;
-        write_answer_table(target, &mut section)?;
+        write_answer_table(
+            target,
+            options,
+            start,
+            (&mut section).zip(&mut offsets),
+        )?;
     }
 
     let section = section.next_section()?.unwrap();
-    if counts.arcount() > 1 || (opt.is_none() && counts.arcount() > 0) {
+    let additional = AdditionalCounts::new(section)?;
+    if additional.opt > 1 {
+        writeln!(
+            target,
+            "\nWARNING: {} OPT records in the additional section",
+            additional.opt
+        )?;
+    }
+    if additional.other > 0 {
         writeln!(target, "\n{BOLD}ADDITIONAL SECTION{RESET}")?;
         write_answer_table(
             target,
-            section.filter(|item| {
+            options,
+            start,
+            section.zip(offsets).filter(|(item, _)| {
                 item.as_ref().map_or(true, |i| i.rtype() != Rtype::OPT)
             }),
         )?;
     }
 
-    write_stats(target, msg, answer)?;
-
     Ok(())
 }
 
@@ -139,7 +183,15 @@ fn write_opt(
                     };
                     ("PADDING", format!("{len} bytes ({all_zero})"))
                 }
-                ClientSubnet(opt) => ("CLIENTSUBNET", opt.to_string()),
+                ClientSubnet(opt) => (
+                    "CLIENTSUBNET",
+                    format!(
+                        "{}/{}, scope /{}",
+                        opt.addr(),
+                        opt.source_prefix_len(),
+                        opt.scope_prefix_len()
+                    ),
+                ),
                 Cookie(cookie) => ("COOKIE: {}", cookie.to_string()),
                 Chain(chain) => ("CHAIN", chain.to_string()),
                 KeyTag(keytag) => ("KEYTAG", keytag.to_string()),
@@ -196,38 +248,244 @@ fn write_question(
 
 fn write_answer_table<'a>(
     target: &mut impl io::Write,
-    answers: impl Iterator<Item = Result<ParsedRecord<'a, &'a [u8]>, ParseError>>,
+    options: &OutputOptions,
+    start: DateTime<Local>,
+    answers: impl Iterator<
+        Item = (
+            Result<ParsedRecord<'a, &'a [u8]>, ParseError>,
+            Option<usize>,
+        ),
+    >,
 ) -> Result<(), OutputError> {
-    let answers = answers
-        .map(|item| {
-            let item = item?;
-            let res = item.to_any_record::<AllRecordData<_, _>>();
-            let data = match res {
-                Ok(item) => item.data().to_string(),
-                Err(_) => "<invalid data>".to_string(),
-            };
-            Ok([
-                item.owner().to_string(),
-                ttl::format(item.ttl()),
-                item.class().to_string(),
-                item.rtype().to_string(),
-                data,
-            ])
-        })
-        .collect::<Result<Vec<_>, OutputError>>()?;
+    let mut rows = Vec::new();
+    for (item, offset) in answers {
+        let item = item?;
+        let res = item.to_any_record::<AllRecordData<_, _>>();
+        let multiline = options.split_record(&item);
+        let data = match (&multiline, res) {
+            (Some(multiline), _) => multiline.first(),
+            (None, Ok(item)) => item.data().to_string(),
+            (None, Err(_)) => "<invalid data>".to_string(),
+        };
+        rows.push([
+            offset.map(format_offset).unwrap_or_default(),
+            item.owner().to_string(),
+            options.ttl_style.format(item.ttl(), start),
+            item.class().to_string(),
+            item.rtype().to_string(),
+            item.rdlen().to_string(),
+            data,
+        ]);
+        let details = multiline
+            .map(|multiline| multiline.tail(""))
+            .unwrap_or_default()
+            .into_iter()
+            .chain(record_details(&item, options));
+        for detail in details {
+            rows.push([
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                format!("  {detail}"),
+            ]);
+        }
+    }
 
     TableWriter {
         indent: "  ",
         spacing: "    ",
-        header: Some(["Owner", "TTL", "Class", "Type", "Data"]),
-        rows: &answers,
-        enabled_columns: [true, true, false, true, true],
-        right_aligned: [false, true, false, false, false],
+        header: Some([
+            "Offset", "Owner", "TTL", "Class", "Type", "RDLen", "Data",
+        ]),
+        rows: &rows,
+        enabled_columns: [
+            options.show_offsets,
+            true,
+            true,
+            false,
+            true,
+            options.show_rdlength,
+            true,
+        ],
+        right_aligned: [false, false, true, false, false, true, false],
+        max_widths: [
+            None,
+            options.max_name_width(),
+            None,
+            None,
+            None,
+            None,
+            options.max_name_width(),
+        ],
     }
     .write(target)?;
     Ok(())
 }
 
+/// Returns additional lines explaining the data of a record.
+fn record_details(
+    item: &ParsedRecord<&[u8]>,
+    options: &OutputOptions,
+) -> Vec<String> {
+    match item.rtype() {
+        Rtype::CAA => caa_details(item),
+        Rtype::SVCB | Rtype::HTTPS => svcb_details(item),
+        _ => options.record_note(item).into_iter().collect(),
+    }
+}
+
+/// Breaks up the data of a CAA record into its fields.
+fn caa_details(item: &ParsedRecord<&[u8]>) -> Vec<String> {
+    let Ok(record) = item.to_any_record::<UnknownRecordData<_>>() else {
+        return Vec::new();
+    };
+    let data: &[u8] = record.data().data();
+    let Some((&flags, data)) = data.split_first() else {
+        return Vec::new();
+    };
+    let Some((&tag_len, data)) = data.split_first() else {
+        return Vec::new();
+    };
+    if data.len() < usize::from(tag_len) {
+        return Vec::new();
+    }
+    let (tag, value) = data.split_at(usize::from(tag_len));
+    let critical = if flags & 0x80 != 0 { " (critical)" } else { "" };
+    vec![
+        format!("flags: {flags}{critical}"),
+        format!("tag:   {}", String::from_utf8_lossy(tag)),
+        format!("value: {}", String::from_utf8_lossy(value)),
+    ]
+}
+
+/// Breaks up the service parameters of an SVCB or HTTPS record.
+///
+/// Each parameter gets a line of its own. Parameters we don’t know are
+/// shown as `keyN=` with their value in hex like RFC 9460 suggests.
+fn svcb_details(item: &ParsedRecord<&[u8]>) -> Vec<String> {
+    let Ok(record) = item.to_any_record::<UnknownRecordData<_>>() else {
+        return Vec::new();
+    };
+    let data: &[u8] = record.data().data();
+
+    // Skip the priority and the target name. The latter must not be
+    // compressed.
+    let mut pos = 2;
+    loop {
+        match data.get(pos) {
+            Some(0) => break,
+            Some(&len) => pos += usize::from(len) + 1,
+            None => return Vec::new(),
+        }
+    }
+    let mut params = &data[pos + 1..];
+
+    let mut rows = Vec::new();
+    while !params.is_empty() {
+        let Some(header) = params.get(..4) else {
+            return Vec::new();
+        };
+        let key = u16::from_be_bytes([header[0], header[1]]);
+        let len = usize::from(u16::from_be_bytes([header[2], header[3]]));
+        let Some(value) = params.get(4..4 + len) else {
+            return Vec::new();
+        };
+        params = &params[4 + len..];
+        rows.push(svc_param(key, value));
+    }
+
+    let width = rows.iter().map(|(key, _)| key.len() + 1).max().unwrap_or(0);
+    rows.into_iter()
+        .map(|(key, value)| {
+            format!("{:<width$} {}", key + ":", value)
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Returns the name and presentation value of a service parameter.
+fn svc_param(key: u16, value: &[u8]) -> (String, String) {
+    let unknown = || {
+        let hex = value
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        (format!("key{}", key), hex)
+    };
+    let res = match key {
+        0 if value.len() % 2 == 0 => Some(
+            value
+                .chunks(2)
+                .map(|key| {
+                    svc_param_name(u16::from_be_bytes([key[0], key[1]]))
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        1 => {
+            let mut ids = Vec::new();
+            let mut rest = value;
+            while let Some((&len, tail)) = rest.split_first() {
+                let Some(id) = tail.get(..usize::from(len)) else {
+                    return unknown();
+                };
+                ids.push(String::from_utf8_lossy(id).into_owned());
+                rest = &tail[usize::from(len)..];
+            }
+            Some(ids.join(", "))
+        }
+        2 if value.is_empty() => Some(String::new()),
+        3 => match value {
+            [hi, lo] => Some(u16::from_be_bytes([*hi, *lo]).to_string()),
+            _ => None,
+        },
+        4 if value.len() % 4 == 0 => Some(
+            value
+                .chunks(4)
+                .map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]).to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        5 => Some(base64::encode_string(value)),
+        6 if value.len() % 16 == 0 => Some(
+            value
+                .chunks(16)
+                .map(|a| {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(a);
+                    Ipv6Addr::from(octets).to_string()
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        7 => Some(String::from_utf8_lossy(value).into_owned()),
+        _ => None,
+    };
+    match res {
+        Some(value) => (svc_param_name(key), value),
+        None => unknown(),
+    }
+}
+
+/// Returns the name of a service parameter key.
+fn svc_param_name(key: u16) -> String {
+    match key {
+        0 => "mandatory".into(),
+        1 => "alpn".into(),
+        2 => "no-default-alpn".into(),
+        3 => "port".into(),
+        4 => "ipv4hint".into(),
+        5 => "ech".into(),
+        6 => "ipv6hint".into(),
+        7 => "dohpath".into(),
+        _ => format!("key{}", key),
+    }
+}
+
 fn write_stats(
     target: &mut impl io::Write,
     msg: Message<&[u8]>,
@@ -235,7 +493,7 @@ fn write_stats(
 ) -> Result<(), OutputError> {
     writeln!(target, "\n{BOLD}EXTRA INFO{RESET}")?;
     let stats = answer.stats();
-    let stats = [
+    let mut rows = vec![
         [
             "When:".into(),
             stats.start.format("%a %b %d %H:%M:%S %Z %Y").to_string(),
@@ -248,20 +506,53 @@ fn write_stats(
             "Server:".into(),
             format!(
                 "{}#{}",
-                stats.server_addr.ip(),
+                super::server_ip(answer),
                 stats.server_addr.port()
             ),
         ],
         ["Protocol:".into(), stats.server_proto.to_string()],
-        [
-            "Response size:".into(),
-            format!("{} bytes", msg.as_slice().len()),
-        ],
     ];
+    if let Some(connect_time) = stats.connect_time {
+        rows.push([
+            "Connect time:".into(),
+            format!("{} msec", connect_time.num_milliseconds()),
+        ]);
+    }
+    if let Some(tls_time) = stats.tls_time {
+        rows.push([
+            "TLS handshake:".into(),
+            format!("{} msec", tls_time.num_milliseconds()),
+        ]);
+    }
+    if let Some(attempts) = stats.attempts {
+        rows.push(["Attempts:".into(), attempts.to_string()]);
+    }
+    rows.push([
+        "Response size:".into(),
+        format!("{} bytes", msg.as_slice().len()),
+    ]);
+    if let Some(tls) = answer.tls() {
+        rows.push(["TLS server name:".into(), tls.server_name.clone()]);
+        rows.push([
+            "TLS version:".into(),
+            tls.version.unwrap_or("unknown").into(),
+        ]);
+        rows.push([
+            "TLS cipher suite:".into(),
+            tls.cipher_suite.as_deref().unwrap_or("unknown").into(),
+        ]);
+        rows.push([
+            "ALPN:".into(),
+            tls.alpn.as_ref().map_or_else(
+                || "none".into(),
+                |alpn| String::from_utf8_lossy(alpn).into_owned(),
+            ),
+        ]);
+    }
 
     TableWriter {
         indent: "  ",
-        rows: &stats,
+        rows: &rows,
         ..Default::default()
     }
     .write(target)?;
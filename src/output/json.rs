@@ -0,0 +1,354 @@
+//! An output format for machines using JSON.
+
+use super::error::OutputError;
+use super::{OutputOptions, Report};
+use crate::client::Answer;
+use domain::base::iana::Rtype;
+use domain::base::message::RecordSection;
+use domain::base::rdata::UnknownRecordData;
+use domain::base::{Message, ParsedRecord};
+use domain::rdata::AllRecordData;
+use serde::Serialize;
+use std::io;
+
+//------------ write ---------------------------------------------------------
+
+/// Writes an answer as a JSON object on a single line.
+///
+/// Next to the message, the object contains the statistics of the query and
+/// details on the connection the answer was received over.
+pub fn write(
+    answer: &Answer,
+    options: &OutputOptions,
+    target: &mut impl io::Write,
+) -> Result<(), OutputError> {
+    let message = JsonMessage::new(answer.msg_slice(), options)?;
+    if options.answer_only {
+        return write_json(&message, target);
+    }
+    write_json(
+        &JsonAnswer {
+            message,
+            stats: JsonStats::new(answer),
+            connection: JsonConnection::new(answer),
+        },
+        target,
+    )
+}
+
+//------------ write_message -------------------------------------------------
+
+/// Writes a message without any information on how it was received.
+pub fn write_message(
+    msg: Message<&[u8]>,
+    options: &OutputOptions,
+    target: &mut impl io::Write,
+) -> Result<(), OutputError> {
+    write_json(&JsonMessage::new(msg, options)?, target)
+}
+
+//------------ write_report --------------------------------------------------
+
+/// Writes the result of a command other than a query.
+pub fn write_report(
+    report: &impl Report,
+    target: &mut dyn io::Write,
+) -> Result<(), io::Error> {
+    serde_json::to_writer(&mut *target, report)?;
+    writeln!(target)
+}
+
+/// Writes a value as JSON followed by a line feed.
+fn write_json(
+    value: &impl Serialize,
+    target: &mut impl io::Write,
+) -> Result<(), OutputError> {
+    serde_json::to_writer(&mut *target, value).map_err(io::Error::from)?;
+    writeln!(target)?;
+    Ok(())
+}
+
+//------------ JsonAnswer ----------------------------------------------------
+
+/// An answer received from a server.
+#[derive(Serialize)]
+struct JsonAnswer {
+    #[serde(flatten)]
+    message: JsonMessage,
+    stats: JsonStats,
+    connection: JsonConnection,
+}
+
+//------------ JsonMessage ---------------------------------------------------
+
+/// A DNS message.
+///
+/// If only the answer is requested, all other parts are left out.
+#[derive(Serialize)]
+struct JsonMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    header: Option<JsonHeader>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edns: Option<JsonEdns>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    question: Option<Vec<JsonQuestion>>,
+    answer: Vec<JsonRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authority: Option<Vec<JsonRecord>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    additional: Option<Vec<JsonRecord>>,
+}
+
+impl JsonMessage {
+    fn new(
+        msg: Message<&[u8]>,
+        options: &OutputOptions,
+    ) -> Result<Self, OutputError> {
+        let answer = msg.answer()?;
+        if options.answer_only {
+            return Ok(JsonMessage {
+                header: None,
+                edns: None,
+                question: None,
+                answer: JsonRecord::section(answer)?,
+                authority: None,
+                additional: None,
+            });
+        }
+
+        let header = msg.header();
+        let authority = answer.next_section()?.unwrap();
+        let additional = authority.next_section()?.unwrap();
+        Ok(JsonMessage {
+            header: Some(JsonHeader {
+                id: header.id(),
+                opcode: header.opcode().to_string(),
+                rcode: header.rcode().to_string(),
+                flags: header.flags().to_string(),
+            }),
+            edns: msg.opt().map(|opt| JsonEdns {
+                version: opt.version(),
+                dnssec_ok: opt.dnssec_ok(),
+                udp_payload_size: opt.udp_payload_size(),
+            }),
+            question: Some(
+                msg.question()
+                    .map(|item| {
+                        item.map(|item| JsonQuestion {
+                            name: item.qname().to_string(),
+                            class: item.qclass().to_string(),
+                            rtype: item.qtype().to_string(),
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            answer: JsonRecord::section(answer)?,
+            authority: Some(JsonRecord::section(authority)?),
+            additional: Some(JsonRecord::section(additional)?),
+        })
+    }
+}
+
+//------------ JsonHeader ----------------------------------------------------
+
+#[derive(Serialize)]
+struct JsonHeader {
+    id: u16,
+    opcode: String,
+    rcode: String,
+    flags: String,
+}
+
+//------------ JsonEdns ------------------------------------------------------
+
+#[derive(Serialize)]
+struct JsonEdns {
+    version: u8,
+    dnssec_ok: bool,
+    udp_payload_size: u16,
+}
+
+//------------ JsonQuestion --------------------------------------------------
+
+#[derive(Serialize)]
+struct JsonQuestion {
+    name: String,
+    class: String,
+    #[serde(rename = "type")]
+    rtype: String,
+}
+
+//------------ JsonRecord ----------------------------------------------------
+
+/// A resource record with its data in presentation format.
+#[derive(Serialize)]
+struct JsonRecord {
+    name: String,
+    ttl: u32,
+    class: String,
+    #[serde(rename = "type")]
+    rtype: String,
+    data: String,
+}
+
+impl JsonRecord {
+    /// Returns all records of a section except for OPT records.
+    fn section(
+        section: RecordSection<&[u8]>,
+    ) -> Result<Vec<Self>, OutputError> {
+        let mut res = Vec::new();
+        for item in section {
+            let item = item?;
+            if item.rtype() != Rtype::OPT {
+                res.push(Self::new(&item));
+            }
+        }
+        Ok(res)
+    }
+
+    fn new(item: &ParsedRecord<&[u8]>) -> Self {
+        // Show data we don’t understand in the generic format of RFC 3597.
+        let data = match item.to_any_record::<AllRecordData<_, _>>() {
+            Ok(record) => record.data().to_string(),
+            Err(_) => match item.to_any_record::<UnknownRecordData<_>>() {
+                Ok(record) => record.data().to_string(),
+                Err(_) => "<invalid data>".into(),
+            },
+        };
+        JsonRecord {
+            name: item.owner().to_string(),
+            ttl: item.ttl().as_secs(),
+            class: item.class().to_string(),
+            rtype: item.rtype().to_string(),
+            data,
+        }
+    }
+}
+
+//------------ JsonStats -----------------------------------------------------
+
+/// The statistics of a query.
+///
+/// All durations are in milliseconds.
+#[derive(Serialize)]
+struct JsonStats {
+    start: String,
+    duration: i64,
+    server: String,
+    port: u16,
+    protocol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u8>,
+}
+
+impl JsonStats {
+    fn new(answer: &Answer) -> Self {
+        let stats = answer.stats();
+        JsonStats {
+            start: stats.start.to_rfc3339(),
+            duration: stats.duration.num_milliseconds(),
+            server: stats.server_addr.ip().to_string(),
+            port: stats.server_addr.port(),
+            protocol: stats.server_proto.to_string(),
+            server_name: answer.server_name().map(Into::into),
+            connect_time: stats.connect_time.map(|t| t.num_milliseconds()),
+            tls_time: stats.tls_time.map(|t| t.num_milliseconds()),
+            attempts: stats.attempts,
+        }
+    }
+}
+
+//------------ JsonConnection ------------------------------------------------
+
+/// How an answer was received.
+///
+/// The TLS details are left out for transports without TLS.
+#[derive(Serialize)]
+struct JsonConnection {
+    transport: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cipher_suite: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alpn: Option<String>,
+}
+
+impl JsonConnection {
+    fn new(answer: &Answer) -> Self {
+        let tls = answer.tls();
+        JsonConnection {
+            transport: answer.stats().server_proto.to_string(),
+            server_name: tls.map(|tls| tls.server_name.clone()),
+            tls_version: tls.and_then(|tls| tls.version).map(Into::into),
+            cipher_suite: tls.and_then(|tls| tls.cipher_suite.clone()),
+            alpn: tls.and_then(|tls| {
+                tls.alpn
+                    .as_ref()
+                    .map(|alpn| String::from_utf8_lossy(alpn).into_owned())
+            }),
+        }
+    }
+}
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+    use domain::base::iana::Class;
+    use domain::base::{MessageBuilder, Name, Record, Ttl};
+    use domain::rdata::A;
+    use serde_json::Value;
+    use std::str::FromStr;
+
+    /// Writes a message with a single A record using the arguments.
+    fn json(args: &[&str]) -> Value {
+        let name = Name::<Vec<u8>>::from_str("example.com").unwrap();
+        let mut msg = MessageBuilder::new_vec();
+        msg.header_mut().set_qr(true);
+        let mut msg = msg.question();
+        msg.push((&name, Rtype::A)).unwrap();
+        let mut msg = msg.answer();
+        let addr = A::new([192, 0, 2, 1].into());
+        msg.push(Record::new(&name, Class::IN, Ttl::from_secs(3600), addr))
+            .unwrap();
+        let msg = msg.into_message();
+
+        let options = OutputOptions::parse_from(["dnsi"].iter().chain(args));
+        let mut target = Vec::new();
+        let res = write_message(msg.for_slice(), &options, &mut target);
+        assert!(res.is_ok());
+        assert_eq!(target.pop(), Some(b'\n'));
+        serde_json::from_slice(&target).unwrap()
+    }
+
+    #[test]
+    fn message() {
+        let json = json(&[]);
+        assert_eq!(json["header"]["rcode"], "NOERROR");
+        assert_eq!(json["question"][0]["type"], "A");
+        assert_eq!(json["answer"][0]["type"], "A");
+        assert_eq!(json["answer"][0]["ttl"], 3600);
+        assert_eq!(json["answer"][0]["data"], "192.0.2.1");
+        assert_eq!(json["authority"], Value::Array(Vec::new()));
+        assert!(json.get("edns").is_none());
+    }
+
+    #[test]
+    fn answer_only() {
+        let json = json(&["--answer-only"]);
+        assert!(json.get("header").is_none());
+        assert!(json.get("question").is_none());
+        assert_eq!(json["answer"][0]["data"], "192.0.2.1");
+    }
+}
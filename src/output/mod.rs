@@ -1,17 +1,39 @@
 //! Message output formats.
 
 mod ansi;
+mod compression;
 mod dig;
 mod error;
 mod friendly;
+mod json;
+mod multiline;
+mod offsets;
+mod short;
 mod table;
 mod table_writer;
 mod ttl;
 
+pub use self::compression::CompressionStats;
+pub use self::table_writer::{Highlight, TableWriter};
+
 use super::client::Answer;
+use super::error::Error;
+use chrono::{DateTime, Local};
 use clap::{Parser, ValueEnum};
+use domain::base::iana::Rtype;
+use domain::base::message::{Message, RecordSection};
+use domain::base::rdata::UnknownRecordData;
+use domain::base::wire::ParseError;
+use domain::base::{ParsedRecord, Ttl};
+use domain::rdata::Aaaa;
 use error::OutputError;
+use multiline::Multiline;
+use serde::Serialize;
+use std::env;
 use std::io;
+use std::io::{IsTerminal, Write as _};
+use std::iter;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 //------------ OutputFormat --------------------------------------------------
 
@@ -25,25 +47,185 @@ pub enum OutputFormat {
 
     /// Short readable format
     Table,
+
+    /// Only the record data of the answer, one record per line
+    Short,
+
+    /// One JSON object per line for each answer
+    Json,
+}
+
+//------------ Color ---------------------------------------------------------
+
+/// When to use ANSI codes for highlighting.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Color {
+    /// Only if stdout is a terminal and NO_COLOR isn’t set
+    Auto,
+
+    /// Always
+    Always,
+
+    /// Never
+    Never,
+}
+
+impl Color {
+    /// Returns whether ANSI codes should be used.
+    pub fn enabled(self) -> bool {
+        match self {
+            Color::Auto => {
+                env::var_os("NO_COLOR").map_or(true, |val| val.is_empty())
+                    && io::stdout().is_terminal()
+            }
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+
+    /// Enables or disables ANSI codes for all following output.
+    pub fn apply(self) {
+        ansi::set_enabled(self.enabled());
+    }
+}
+
+//------------ TtlStyle ------------------------------------------------------
+
+/// How to show the TTL of records.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum TtlStyle {
+    /// In days, hours, minutes, and seconds
+    Human,
+
+    /// As the number of seconds
+    Seconds,
+
+    /// As the time the record expires
+    Absolute,
+}
+
+impl TtlStyle {
+    /// Formats a TTL counting from the given start time.
+    pub fn format(self, ttl: Ttl, start: DateTime<Local>) -> String {
+        match self {
+            Self::Human => self::ttl::format(ttl),
+            Self::Seconds => ttl.as_secs().to_string(),
+            Self::Absolute => self::ttl::format_expiry(ttl, start),
+        }
+    }
 }
 
+//------------ OutputOptions -------------------------------------------------
+
 #[derive(Clone, Debug, Parser)]
 pub struct OutputOptions {
     #[arg(long = "format", default_value = "friendly")]
     pub format: OutputFormat,
+
+    /// Show the length of the record data of each record.
+    #[arg(long)]
+    pub show_rdlength: bool,
+
+    /// Only show the records of the answer section.
+    #[arg(long)]
+    pub answer_only: bool,
+
+    /// Show the offset of each record within the message.
+    #[arg(long)]
+    pub show_offsets: bool,
+
+    /// Spread SOA, DNSKEY, RRSIG, and NSEC3PARAM data over several lines.
+    #[arg(long)]
+    pub multiline: bool,
+
+    /// Flush the output after every line rather than after every answer.
+    #[arg(long)]
+    pub line_buffered: bool,
+
+    /// Cut owner names and record data to this width on a terminal.
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        num_args = 0..=1,
+        default_missing_value = "40"
+    )]
+    pub truncate_names: Option<usize>,
+
+    /// Mark AAAA records in this /96 prefix as synthesized by DNS64.
+    #[arg(long, value_name = "PREFIX", default_value = "64:ff9b::")]
+    pub dns64_prefix: Ipv6Addr,
+
+    /// How to show TTLs in the friendly and table formats.
+    #[arg(long, value_name = "STYLE", default_value = "human")]
+    pub ttl_style: TtlStyle,
+
+    /// When to highlight the output with ANSI codes.
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    pub color: Color,
+
+    /// Don’t highlight the output. Same as --color never.
+    #[arg(long, conflicts_with = "color")]
+    pub no_color: bool,
 }
 
-impl OutputFormat {
+impl OutputOptions {
     pub fn write(
-        self,
+        &self,
         msg: &Answer,
         target: &mut impl io::Write,
     ) -> Result<(), io::Error> {
-        let res = match self {
-            Self::Dig => self::dig::write(msg, target),
-            Self::Friendly => self::friendly::write(msg, target),
-            Self::Table => self::table::write(msg, target),
+        self.color().apply();
+        let res = match self.format {
+            OutputFormat::Dig => self::dig::write(msg, self, target),
+            OutputFormat::Friendly => {
+                self::friendly::write(msg, self, target)
+            }
+            OutputFormat::Table => self::table::write(
+                msg.msg_slice(),
+                msg.stats().start,
+                self,
+                target,
+            ),
+            OutputFormat::Short => {
+                self::short::write(msg.msg_slice(), self, target)
+            }
+            OutputFormat::Json => self::json::write(msg, self, target),
+        };
+        Self::finish(res, target)
+    }
+
+    /// Writes a message that wasn’t received from a server.
+    ///
+    /// Since there are no statistics, only the message itself is written.
+    /// Absolute TTLs count from now.
+    pub fn write_message(
+        &self,
+        msg: Message<&[u8]>,
+        target: &mut impl io::Write,
+    ) -> Result<(), io::Error> {
+        self.color().apply();
+        let start = Local::now();
+        let res = match self.format {
+            OutputFormat::Dig => self::dig::write_message(msg, self, target),
+            OutputFormat::Friendly => {
+                self::friendly::write_message(msg, start, self, target)
+            }
+            OutputFormat::Table => {
+                self::table::write(msg, start, self, target)
+            }
+            OutputFormat::Short => self::short::write(msg, self, target),
+            OutputFormat::Json => {
+                self::json::write_message(msg, self, target)
+            }
         };
+        Self::finish(res, target)
+    }
+
+    /// Reports a malformed message in the output instead of failing.
+    fn finish(
+        res: Result<(), OutputError>,
+        target: &mut impl io::Write,
+    ) -> Result<(), io::Error> {
         match res {
             Ok(()) => Ok(()),
             Err(OutputError::Io(e)) => Err(e),
@@ -54,7 +236,198 @@ impl OutputFormat {
         }
     }
 
-    pub fn print(self, msg: &Answer) -> Result<(), io::Error> {
-        self.write(msg, &mut io::stdout().lock())
+    /// Returns when the output should contain ANSI codes.
+    fn color(&self) -> Color {
+        if self.no_color {
+            Color::Never
+        } else {
+            self.color
+        }
+    }
+
+    /// Returns the maximum width for owner names and record data.
+    ///
+    /// Values are only cut short when writing to a terminal.
+    fn max_name_width(&self) -> Option<usize> {
+        self.truncate_names.filter(|_| io::stdout().is_terminal())
+    }
+
+    /// Returns the offsets of the records in a message if they are shown.
+    ///
+    /// The iterator yields the offset for each record in order or `None`
+    /// if offsets aren’t shown or the message is malformed.
+    fn record_offsets(
+        &self,
+        msg: &[u8],
+    ) -> impl Iterator<Item = Option<usize>> {
+        let offsets = if self.show_offsets {
+            offsets::record_offsets(msg)
+        } else {
+            None
+        };
+        offsets
+            .unwrap_or_default()
+            .into_iter()
+            .map(Some)
+            .chain(iter::repeat(None))
+    }
+
+    /// Returns the data of a record split over several lines if requested.
+    fn split_record(&self, item: &ParsedRecord<&[u8]>) -> Option<Multiline> {
+        if self.multiline {
+            Multiline::new(item)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a note explaining the meaning of a record if it needs one.
+    fn record_note(&self, item: &ParsedRecord<&[u8]>) -> Option<String> {
+        match item.rtype() {
+            Rtype::AAAA => self
+                .dns64_source(item)
+                .map(|addr| format!("synthesized from {addr}")),
+            Rtype::HINFO if is_rfc8482(item) => {
+                Some("ANY-minimized response (RFC 8482)".into())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the IPv4 address a DNS64 AAAA record was synthesized from.
+    ///
+    /// Returns `None` if the record isn’t an AAAA record or its address
+    /// isn’t within the DNS64 prefix.
+    fn dns64_source(&self, item: &ParsedRecord<&[u8]>) -> Option<Ipv4Addr> {
+        let record = item.to_record::<Aaaa>().ok()??;
+        let octets = record.data().addr().octets();
+        if octets[..12] != self.dns64_prefix.octets()[..12] {
+            return None;
+        }
+        Some(Ipv4Addr::new(
+            octets[12], octets[13], octets[14], octets[15],
+        ))
+    }
+
+    /// Prints an answer to stdout.
+    ///
+    /// Output is flushed once the answer is complete so that consumers
+    /// reading from a pipe see each answer as soon as it is available.
+    pub fn print(&self, msg: &Answer) -> Result<(), Error> {
+        self.print_with(|target| self.write(msg, target))
+    }
+
+    /// Prints a message that wasn’t received from a server to stdout.
+    pub fn print_message(&self, msg: Message<&[u8]>) -> Result<(), Error> {
+        self.print_with(|target| self.write_message(msg, target))
+    }
+
+    /// Prints the result of a command other than a query to stdout.
+    ///
+    /// The dig, friendly, and table formats all print the result as text.
+    pub fn print_report(&self, report: &impl Report) -> Result<(), Error> {
+        self.print_with(|target| match self.format {
+            OutputFormat::Dig
+            | OutputFormat::Friendly
+            | OutputFormat::Table => report.write_text(target),
+            OutputFormat::Short => report.write_short(target),
+            OutputFormat::Json => self::json::write_report(report, target),
+        })
+    }
+
+    /// Runs `op` on stdout and flushes the output afterwards.
+    fn print_with(
+        &self,
+        op: impl FnOnce(&mut Box<dyn io::Write>) -> Result<(), io::Error>,
+    ) -> Result<(), Error> {
+        let stdout = io::stdout().lock();
+        let mut target: Box<dyn io::Write> = if self.line_buffered {
+            // Stdout itself is line buffered.
+            Box::new(stdout)
+        } else {
+            Box::new(io::BufWriter::new(stdout))
+        };
+        op(&mut target).map_err(Error::output)?;
+        target.flush().map_err(Error::output)
+    }
+}
+
+//------------ Report --------------------------------------------------------
+
+/// The result of a command that isn’t a DNS message.
+pub trait Report: Serialize {
+    /// Writes the result as human readable text.
+    fn write_text(&self, target: &mut dyn io::Write)
+        -> Result<(), io::Error>;
+
+    /// Writes only the essential data, one item per line.
+    fn write_short(
+        &self,
+        target: &mut dyn io::Write,
+    ) -> Result<(), io::Error>;
+}
+
+//------------ format_offset -------------------------------------------------
+
+/// Formats the offset of a record like a hex dump would.
+fn format_offset(offset: usize) -> String {
+    format!("{:#06x}", offset)
+}
+
+//------------ is_rfc8482 ----------------------------------------------------
+
+/// Returns whether a record is the HINFO record of a minimal ANY response.
+///
+/// RFC 8482 suggests answering ANY queries with a single HINFO record with
+/// a CPU field of "RFC8482".
+fn is_rfc8482(item: &ParsedRecord<&[u8]>) -> bool {
+    let Ok(record) = item.to_any_record::<UnknownRecordData<_>>() else {
+        return false;
+    };
+    let data: &[u8] = record.data().data();
+    match data.split_first() {
+        Some((&len, data)) => data
+            .get(..usize::from(len))
+            .is_some_and(|cpu| cpu.eq_ignore_ascii_case(b"RFC8482")),
+        None => false,
+    }
+}
+
+//------------ server_ip -----------------------------------------------------
+
+/// Returns the server’s IP address with its host name if that is known.
+fn server_ip(answer: &Answer) -> String {
+    let ip = answer.stats().server_addr.ip();
+    match answer.server_name() {
+        Some(name) => format!("{} ({})", ip, name),
+        None => ip.to_string(),
+    }
+}
+
+//------------ AdditionalCounts ----------------------------------------------
+
+/// The number of records in the additional section by kind.
+///
+/// A message must contain at most one OPT record. This is only true for
+/// well-formed messages, though, so we count them.
+struct AdditionalCounts {
+    /// The number of OPT records.
+    opt: usize,
+
+    /// The number of all other records.
+    other: usize,
+}
+
+impl AdditionalCounts {
+    fn new(section: RecordSection<&[u8]>) -> Result<Self, ParseError> {
+        let mut res = AdditionalCounts { opt: 0, other: 0 };
+        for item in section {
+            if item?.rtype() == Rtype::OPT {
+                res.opt += 1;
+            } else {
+                res.other += 1;
+            }
+        }
+        Ok(res)
     }
 }
@@ -0,0 +1,202 @@
+//! Spreading the data of long records over several lines.
+
+use super::ttl;
+use domain::base::iana::Rtype;
+use domain::base::{ParsedRecord, Ttl};
+use domain::rdata::{Dnskey, Nsec3param, Rrsig, Soa};
+use domain::utils::base64;
+
+/// The number of characters of base64 data per line.
+const BASE64_WIDTH: usize = 44;
+
+//------------ Multiline -----------------------------------------------------
+
+/// The data of a record split into several lines.
+///
+/// This follows the `+multiline` option of dig: the first few fields stay
+/// on the line with the record type, the others each get a line of their
+/// own, possibly with a comment explaining them.
+#[derive(Clone, Debug)]
+pub struct Multiline {
+    /// The fields that stay on the first line.
+    pub head: String,
+
+    /// The remaining fields and their comments.
+    pub lines: Vec<(String, Option<String>)>,
+
+    /// A comment on the record as a whole.
+    pub comment: Option<String>,
+}
+
+impl Multiline {
+    /// Splits the data of a record if it is of a supported type.
+    ///
+    /// Returns `None` for all other types and if the data is malformed.
+    pub fn new(item: &ParsedRecord<&[u8]>) -> Option<Self> {
+        match item.rtype() {
+            Rtype::SOA => Self::soa(item),
+            Rtype::DNSKEY => Self::dnskey(item),
+            Rtype::RRSIG => Self::rrsig(item),
+            Rtype::NSEC3PARAM => Self::nsec3param(item),
+            _ => None,
+        }
+    }
+
+    fn soa(item: &ParsedRecord<&[u8]>) -> Option<Self> {
+        let record = item.to_record::<Soa<_>>().ok()??;
+        let soa = record.data();
+        let interval = |name: &str, ttl: Ttl| {
+            Some(format!("{} ({})", name, format_interval(ttl)))
+        };
+        Some(Multiline {
+            head: format!("{} {}", soa.mname(), soa.rname()),
+            lines: vec![
+                (soa.serial().to_string(), Some("serial".into())),
+                (
+                    soa.refresh().as_secs().to_string(),
+                    interval("refresh", soa.refresh()),
+                ),
+                (
+                    soa.retry().as_secs().to_string(),
+                    interval("retry", soa.retry()),
+                ),
+                (
+                    soa.expire().as_secs().to_string(),
+                    interval("expire", soa.expire()),
+                ),
+                (
+                    soa.minimum().as_secs().to_string(),
+                    interval("minimum", soa.minimum()),
+                ),
+            ],
+            comment: None,
+        })
+    }
+
+    fn dnskey(item: &ParsedRecord<&[u8]>) -> Option<Self> {
+        let record = item.to_record::<Dnskey<_>>().ok()??;
+        let key = record.data();
+        let kind = if key.flags() & 0x0001 != 0 {
+            "KSK"
+        } else {
+            "ZSK"
+        };
+        Some(Multiline {
+            head: format!(
+                "{} {} {}",
+                key.flags(),
+                key.protocol(),
+                key.algorithm().to_int()
+            ),
+            lines: base64_lines(key.public_key().as_ref()),
+            comment: Some(format!(
+                "{}; alg = {}; key id = {}",
+                kind,
+                key.algorithm(),
+                key.key_tag()
+            )),
+        })
+    }
+
+    fn rrsig(item: &ParsedRecord<&[u8]>) -> Option<Self> {
+        let record = item.to_record::<Rrsig<_, _>>().ok()??;
+        let sig = record.data();
+        let mut lines = vec![
+            (sig.expiration().to_string(), Some("expiration".into())),
+            (sig.inception().to_string(), Some("inception".into())),
+            (sig.key_tag().to_string(), Some("key id".into())),
+            (sig.signer_name().to_string(), Some("signer".into())),
+        ];
+        lines.extend(base64_lines(sig.signature().as_ref()));
+        Some(Multiline {
+            head: format!(
+                "{} {} {} {}",
+                sig.type_covered(),
+                sig.algorithm().to_int(),
+                sig.labels(),
+                sig.original_ttl().as_secs()
+            ),
+            lines,
+            comment: None,
+        })
+    }
+
+    fn nsec3param(item: &ParsedRecord<&[u8]>) -> Option<Self> {
+        let record = item.to_record::<Nsec3param<_>>().ok()??;
+        let param = record.data();
+        Some(Multiline {
+            head: String::new(),
+            lines: vec![
+                (
+                    param.hash_algorithm().to_int().to_string(),
+                    Some(format!(
+                        "hash algorithm ({})",
+                        param.hash_algorithm()
+                    )),
+                ),
+                (param.flags().to_string(), Some("flags".into())),
+                (param.iterations().to_string(), Some("iterations".into())),
+                (param.salt().to_string(), Some("salt".into())),
+            ],
+            comment: None,
+        })
+    }
+
+    /// Returns the lines after the first one, each with the given indent.
+    ///
+    /// The comments of the fields are aligned. The last line closes the
+    /// parenthesis opened on the first line and carries the record’s
+    /// comment.
+    pub fn tail(&self, indent: &str) -> Vec<String> {
+        let width = self
+            .lines
+            .iter()
+            .filter(|(_, comment)| comment.is_some())
+            .map(|(value, _)| value.len())
+            .max()
+            .unwrap_or(0);
+        let mut res = self
+            .lines
+            .iter()
+            .map(|(value, comment)| match comment {
+                Some(comment) => {
+                    format!("{indent}{value:<width$} ; {comment}")
+                }
+                None => format!("{indent}{value}"),
+            })
+            .collect::<Vec<_>>();
+        res.push(match self.comment.as_ref() {
+            Some(comment) => format!("{indent}) ; {comment}"),
+            None => format!("{indent})"),
+        });
+        res
+    }
+
+    /// Returns the first line of the data.
+    pub fn first(&self) -> String {
+        if self.head.is_empty() {
+            "(".into()
+        } else {
+            format!("{} (", self.head)
+        }
+    }
+}
+
+//------------ Helpers -------------------------------------------------------
+
+/// Formats an interval in seconds the short way.
+fn format_interval(ttl: Ttl) -> String {
+    ttl::format(ttl)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Breaks binary data into lines of base64.
+fn base64_lines(data: &[u8]) -> Vec<(String, Option<String>)> {
+    base64::encode_string(data)
+        .as_bytes()
+        .chunks(BASE64_WIDTH)
+        .map(|chunk| (String::from_utf8_lossy(chunk).into_owned(), None))
+        .collect()
+}
@@ -0,0 +1,50 @@
+//! Finding the positions of records in a message.
+
+//------------ record_offsets ------------------------------------------------
+
+/// Returns the offsets of all records in the given wire-format message.
+///
+/// The offsets are those of the first octet of each record’s owner name,
+/// in the order the records appear in the answer, authority, and
+/// additional sections.
+///
+/// Returns `None` if the message is malformed.
+pub fn record_offsets(msg: &[u8]) -> Option<Vec<usize>> {
+    let count = |pos: usize| {
+        msg.get(pos..pos + 2)
+            .map(|s| usize::from(u16::from_be_bytes([s[0], s[1]])))
+    };
+    let qdcount = count(4)?;
+    let rrcount = count(6)? + count(8)? + count(10)?;
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(msg, pos)? + 4;
+    }
+    let mut res = Vec::with_capacity(rrcount);
+    for _ in 0..rrcount {
+        res.push(pos);
+        pos = skip_name(msg, pos)?;
+        pos += 10 + count(pos + 8)?;
+        if pos > msg.len() {
+            return None;
+        }
+    }
+    Some(res)
+}
+
+/// Returns the position of the first octet after the name at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)?;
+        match len & 0xC0 {
+            0x00 if len == 0 => return Some(pos + 1),
+            0x00 => pos += usize::from(len) + 1,
+            0xC0 => {
+                msg.get(pos + 1)?;
+                return Some(pos + 2);
+            }
+            _ => return None,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! An output format with only the record data of the answer.
+
+use super::error::OutputError;
+use super::OutputOptions;
+use domain::base::iana::Rtype;
+use domain::base::Message;
+use domain::rdata::AllRecordData;
+use std::io;
+
+//------------ write ---------------------------------------------------------
+
+/// Writes the record data of the answer section, one record per line.
+///
+/// Like dig’s `+short`, this is intended for use in scripts. Records with
+/// data we don’t understand are skipped.
+pub fn write(
+    msg: Message<&[u8]>,
+    _options: &OutputOptions,
+    target: &mut impl io::Write,
+) -> Result<(), OutputError> {
+    for item in msg.answer()? {
+        let item = item?;
+        if item.rtype() == Rtype::OPT {
+            continue;
+        }
+        if let Ok(record) = item.to_any_record::<AllRecordData<_, _>>() {
+            writeln!(target, "{}", record.data())?;
+        }
+    }
+    Ok(())
+}
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+    use domain::base::iana::Class;
+    use domain::base::{MessageBuilder, Name, Record, Ttl};
+    use domain::rdata::{Ns, A};
+    use std::str::FromStr;
+
+    #[test]
+    fn only_answer_data() {
+        let name = Name::<Vec<u8>>::from_str("example.com").unwrap();
+        let ns = Name::<Vec<u8>>::from_str("ns.example.com").unwrap();
+        let ttl = Ttl::from_secs(3600);
+
+        let mut msg = MessageBuilder::new_vec();
+        msg.header_mut().set_qr(true);
+        let mut msg = msg.question();
+        msg.push((&name, Rtype::A)).unwrap();
+        let mut msg = msg.answer();
+        for addr in [[192, 0, 2, 1], [192, 0, 2, 2]] {
+            msg.push(Record::new(&name, Class::IN, ttl, A::new(addr.into())))
+                .unwrap();
+        }
+        let mut msg = msg.authority();
+        msg.push(Record::new(&name, Class::IN, ttl, Ns::new(&ns)))
+            .unwrap();
+        let mut msg = msg.additional();
+        let addr = A::new([192, 0, 2, 53].into());
+        msg.push(Record::new(&ns, Class::IN, ttl, addr)).unwrap();
+        let msg = msg.into_message();
+
+        let mut target = Vec::new();
+        assert!(write(
+            msg.for_slice(),
+            &OutputOptions::parse_from(["dnsi"]),
+            &mut target,
+        )
+        .is_ok());
+        assert_eq!(
+            String::from_utf8(target).unwrap(),
+            "192.0.2.1\n192.0.2.2\n"
+        );
+    }
+}
@@ -1,28 +1,37 @@
 use std::io;
 
-use domain::base::Rtype;
+use chrono::{DateTime, Local};
+use domain::base::{Message, Rtype};
 use domain::rdata::AllRecordData;
 
-use super::{error::OutputError, ttl};
-use crate::{client::Answer, output::table_writer::TableWriter};
+use super::{error::OutputError, format_offset, OutputOptions};
+use crate::output::table_writer::TableWriter;
 
 pub fn write(
-    answer: &Answer,
+    msg: Message<&[u8]>,
+    start: DateTime<Local>,
+    options: &OutputOptions,
     target: &mut impl io::Write,
 ) -> Result<(), OutputError> {
-    let msg = answer.msg_slice();
-
     let mut table_rows = Vec::new();
 
     const SECTION_NAMES: [&str; 3] = ["ANSWER", "AUTHORITY", "ADDITIONAL"];
     let mut section = msg.question().answer()?;
+    let count = if options.answer_only {
+        1
+    } else {
+        SECTION_NAMES.len()
+    };
+
+    let mut offsets = options.record_offsets(msg.as_slice());
 
-    for name in SECTION_NAMES {
-        let mut iter = section
-            .filter(|i| i.as_ref().map_or(true, |i| i.rtype() != Rtype::OPT));
+    for name in SECTION_NAMES.into_iter().take(count) {
+        let mut iter = section.zip(&mut offsets).filter(|(i, _)| {
+            i.as_ref().map_or(true, |i| i.rtype() != Rtype::OPT)
+        });
 
         // The first row of each section gets the section name
-        if let Some(row) = iter.next() {
+        if let Some((row, offset)) = iter.next() {
             let row = row?;
             let data = match row.to_any_record::<AllRecordData<_, _>>() {
                 Ok(row) => row.data().to_string(),
@@ -30,16 +39,18 @@ pub fn write(
             };
             table_rows.push([
                 name.into(),
+                offset.map(format_offset).unwrap_or_default(),
                 row.owner().to_string(),
-                ttl::format(row.ttl()),
+                options.ttl_style.format(row.ttl(), start),
                 row.class().to_string(),
                 row.rtype().to_string(),
+                row.rdlen().to_string(),
                 data,
             ]);
         }
 
         // The rest of the rows we show without section name
-        for row in &mut iter {
+        for (row, offset) in &mut iter {
             let row = row?;
             let data = match row.to_any_record::<AllRecordData<_, _>>() {
                 Ok(row) => row.data().to_string(),
@@ -47,10 +58,12 @@ pub fn write(
             };
             table_rows.push([
                 String::new(),
+                offset.map(format_offset).unwrap_or_default(),
                 row.owner().to_string(),
-                ttl::format(row.ttl()),
+                options.ttl_style.format(row.ttl(), start),
                 row.class().to_string(),
                 row.rtype().to_string(),
+                row.rdlen().to_string(),
                 data,
             ]);
         }
@@ -63,10 +76,32 @@ pub fn write(
 
     TableWriter {
         spacing: "    ",
-        header: Some(["Section", "Owner", "TTL", "Class", "Type", "Data"]),
+        header: Some([
+            "Section", "Offset", "Owner", "TTL", "Class", "Type", "RDLen",
+            "Data",
+        ]),
         rows: &table_rows,
-        enabled_columns: [true, true, true, false, true, true],
-        right_aligned: [false, false, true, false, false, false],
+        enabled_columns: [
+            true,
+            options.show_offsets,
+            true,
+            true,
+            false,
+            true,
+            options.show_rdlength,
+            true,
+        ],
+        right_aligned: [false, false, false, true, false, false, true, false],
+        max_widths: [
+            None,
+            None,
+            options.max_name_width(),
+            None,
+            None,
+            None,
+            None,
+            options.max_name_width(),
+        ],
         ..Default::default()
     }
     .write(target)?;
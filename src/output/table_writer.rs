@@ -1,6 +1,7 @@
+use std::borrow::Cow;
 use std::io;
 
-use super::ansi::{ITALIC, RESET, UNDERLINE};
+use super::ansi::{Code, ITALIC, RED, RESET, UNDERLINE, YELLOW};
 
 pub struct TableWriter<'a, const N: usize> {
     pub indent: &'a str,
@@ -9,6 +10,10 @@ pub struct TableWriter<'a, const N: usize> {
     pub rows: &'a [[String; N]],
     pub enabled_columns: [bool; N],
     pub right_aligned: [bool; N],
+    /// The maximum width of each column. Longer cells are cut short.
+    pub max_widths: [Option<usize>; N],
+    /// How to highlight each row. Rows without an entry aren’t.
+    pub highlights: &'a [Highlight],
 }
 
 impl<const N: usize> Default for TableWriter<'_, N> {
@@ -20,6 +25,8 @@ impl<const N: usize> Default for TableWriter<'_, N> {
             rows: &[],
             enabled_columns: [true; N],
             right_aligned: [false; N],
+            max_widths: [None; N],
+            highlights: &[],
         }
     }
 }
@@ -33,6 +40,8 @@ impl<const N: usize> TableWriter<'_, N> {
             rows,
             enabled_columns,
             right_aligned,
+            max_widths: _,
+            highlights,
         } = self;
 
         // This contains the widths of all the columns, which we need to
@@ -42,13 +51,14 @@ impl<const N: usize> TableWriter<'_, N> {
         // The header contributes to the widths only if its present.
         if let Some(header) = header {
             for i in 0..N {
-                widths[i] = header[i].len();
+                widths[i] = header[i].chars().count();
             }
         }
 
         for row in *rows {
             for i in 0..N {
-                widths[i] = widths[i].max(row[i].len());
+                let width = self.cell(i, &row[i]).chars().count();
+                widths[i] = widths[i].max(width);
             }
         }
 
@@ -74,16 +84,21 @@ impl<const N: usize> TableWriter<'_, N> {
             writeln!(target, "{RESET}")?;
         }
 
-        for row in *rows {
+        for (j, row) in rows.iter().enumerate() {
             // First we write the indent of the entire table
             write!(target, "{indent}")?;
+            let code = highlights.get(j).and_then(|hl| hl.code());
+            if let Some(code) = code {
+                write!(target, "{code}")?;
+            }
 
             // Print every cell in this row **except** the last cell
             for &i in &columns[..columns.len() - 1] {
+                let cell = self.cell(i, &row[i]);
                 if right_aligned[i] {
-                    write!(target, "{:>width$}", row[i], width = widths[i])?;
+                    write!(target, "{:>width$}", cell, width = widths[i])?;
                 } else {
-                    write!(target, "{:<width$}", row[i], width = widths[i])?;
+                    write!(target, "{:<width$}", cell, width = widths[i])?;
                 }
                 write!(target, "{spacing}")?;
             }
@@ -92,16 +107,16 @@ impl<const N: usize> TableWriter<'_, N> {
             // doesn't need spacing. It also does not need padding if it is
             // left-aligned.
             let last = columns[columns.len() - 1];
+            let cell = self.cell(last, &row[last]);
             if right_aligned[last] {
-                write!(
-                    target,
-                    "{:>width$}",
-                    row[last],
-                    width = widths[last]
-                )?;
+                write!(target, "{:>width$}", cell, width = widths[last])?;
             } else {
                 // It's the last cell; no padding needed
-                write!(target, "{}", row[last],)?;
+                write!(target, "{}", cell)?;
+            }
+
+            if code.is_some() {
+                write!(target, "{RESET}")?;
             }
 
             // Print a newline
@@ -110,4 +125,44 @@ impl<const N: usize> TableWriter<'_, N> {
 
         Ok(())
     }
+
+    /// Returns the content of a cell in column `i`, cut to the max width.
+    fn cell<'s>(&self, i: usize, content: &'s str) -> Cow<'s, str> {
+        match self.max_widths[i] {
+            Some(max) if content.chars().count() > max => {
+                let mut res: String =
+                    content.chars().take(max.saturating_sub(1)).collect();
+                res.push('…');
+                Cow::Owned(res)
+            }
+            _ => Cow::Borrowed(content),
+        }
+    }
+}
+
+//------------ Highlight -----------------------------------------------------
+
+/// How a row of a table is highlighted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Highlight {
+    /// The row is printed normally.
+    #[default]
+    None,
+
+    /// The row needs attention.
+    Warning,
+
+    /// The row reports a failure.
+    Error,
+}
+
+impl Highlight {
+    /// Returns the ANSI code to start the highlight with.
+    fn code(self) -> Option<Code> {
+        match self {
+            Self::None => None,
+            Self::Warning => Some(YELLOW),
+            Self::Error => Some(RED),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 //! Utility functions for formatting the TTL
 
+use chrono::{DateTime, Local, SecondsFormat, TimeDelta};
 use domain::base::Ttl;
 use std::fmt::Write as _;
 
@@ -32,3 +33,11 @@ pub fn format(ttl: Ttl) -> String {
 
     s
 }
+
+/// Formats the time a record expires as an RFC 3339 timestamp.
+///
+/// The TTL counts from `start`, the time the query was sent.
+pub fn format_expiry(ttl: Ttl, start: DateTime<Local>) -> String {
+    (start + TimeDelta::seconds(ttl.as_secs().into()))
+        .to_rfc3339_opts(SecondsFormat::Secs, false)
+}